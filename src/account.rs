@@ -1,14 +1,22 @@
 use rust_decimal::Decimal;
-use serde::{Serialize, ser::SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, ser::SerializeStruct};
 
 use crate::ClientId;
+use crate::config::RoundingMode;
 
-const DECIMAL_PLACES: u32 = 4;
+/// Decimal places balances are written with unless configured otherwise.
+pub const DECIMAL_PLACES: u32 = 4;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
 pub struct Account {
-    pub id: ClientId,
+    /// Client owning the account, `None` until the account is bound to a
+    /// client so that client `0` is never mistaken for an unset id.
+    #[serde(rename = "client", deserialize_with = "deserialize_client")]
+    pub id: Option<ClientId>,
     pub available: Decimal,
+    /// Funds the ledger is holding, from disputed deposits or deposits
+    /// under a settlement hold. Withdrawals cannot be disputed, so `held`
+    /// never stands for funds owed by the client.
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
@@ -17,7 +25,7 @@ pub struct Account {
 impl Default for Account {
     fn default() -> Self {
         Account {
-            id: 0,
+            id: None,
             available: Decimal::new(0, DECIMAL_PLACES),
             held: Decimal::new(0, DECIMAL_PLACES),
             total: Decimal::new(0, DECIMAL_PLACES),
@@ -26,19 +34,261 @@ impl Default for Account {
     }
 }
 
+impl Account {
+    /// Creates an empty account bound to client `id`, accounts created by
+    /// the ledger always go through here so they are never left without a
+    /// client.
+    pub fn new(id: ClientId) -> Self {
+        Account {
+            id: Some(id),
+            ..Account::default()
+        }
+    }
+
+    /// Whether the account holds no funds and is unlocked, a locked account
+    /// is never empty as its lock is material.
+    pub fn is_empty(&self) -> bool {
+        self.available.is_zero() && self.held.is_zero() && self.total.is_zero() && !self.locked
+    }
+
+    /// Whether the balances satisfy `total == available + held`.
+    pub fn check_invariant(&self) -> bool {
+        self.total == self.available + self.held
+    }
+}
+
+/// Read-only copy of an account balances, handed out to callers so they do
+/// not hold a borrow of the ledger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Balance {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl From<&Account> for Balance {
+    fn from(acct: &Account) -> Self {
+        Balance {
+            available: acct.available,
+            held: acct.held,
+            total: acct.total,
+            locked: acct.locked,
+        }
+    }
+}
+
+/// Rows read from a CSV file always carry a client id.
+fn deserialize_client<'de, D>(deserializer: D) -> std::result::Result<Option<ClientId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ClientId::deserialize(deserializer).map(Some)
+}
+
 impl Serialize for Account {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
+        WithPrecision(self, DECIMAL_PLACES, RoundingMode::default()).serialize(serializer)
+    }
+}
+
+/// Serializes an account with balances written with the given number of
+/// decimal places, rounded with the given mode.
+pub struct WithPrecision<'a>(pub &'a Account, pub u32, pub RoundingMode);
+
+impl Serialize for WithPrecision<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let WithPrecision(acct, precision, rounding) = *self;
+        let mut state = serializer.serialize_struct("Account", 5)?;
+
+        state.serialize_field("client", &acct.id)?;
+        state.serialize_field(
+            "available",
+            &format_amount(acct.available, precision, rounding),
+        )?;
+        state.serialize_field("held", &format_amount(acct.held, precision, rounding))?;
+        state.serialize_field("total", &format_amount(acct.total, precision, rounding))?;
+        state.serialize_field("locked", &acct.locked)?;
+
+        state.end()
+    }
+}
+
+/// Formats an amount with exactly `precision` decimal places, rounding with
+/// `rounding`. Balances themselves are never rounded.
+pub fn format_amount(amount: Decimal, precision: u32, rounding: RoundingMode) -> String {
+    let rounded = amount.round_dp_with_strategy(precision, rounding.strategy());
+
+    format!("{:.*}", precision as usize, rounded)
+}
+
+/// Serializes an account with balances as integers in the smallest unit,
+/// i.e. scaled by `10^precision`, for consumers that avoid decimal strings.
+pub struct MinorUnits<'a>(pub &'a Account, pub u32, pub RoundingMode);
+
+impl Serialize for MinorUnits<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let MinorUnits(acct, precision, rounding) = *self;
         let mut state = serializer.serialize_struct("Account", 5)?;
 
-        state.serialize_field("client", &self.id)?;
-        state.serialize_field("available", &format!("{:.4}", self.available))?;
-        state.serialize_field("held", &format!("{:.4}", self.held))?;
-        state.serialize_field("total", &format!("{:.4}", self.total))?;
-        state.serialize_field("locked", &self.locked)?;
+        state.serialize_field("client", &acct.id)?;
+        state.serialize_field(
+            "available",
+            &to_minor_units(acct.available, precision, rounding),
+        )?;
+        state.serialize_field("held", &to_minor_units(acct.held, precision, rounding))?;
+        state.serialize_field("total", &to_minor_units(acct.total, precision, rounding))?;
+        state.serialize_field("locked", &acct.locked)?;
 
         state.end()
     }
 }
+
+/// Converts an amount into units of `10^-precision`, exact for amounts with
+/// at most `precision` decimals and rounded like the decimal output
+/// otherwise.
+pub fn to_minor_units(amount: Decimal, precision: u32, rounding: RoundingMode) -> i128 {
+    let mut amount = amount.round_dp_with_strategy(precision, rounding.strategy());
+    amount.rescale(precision);
+    amount.mantissa()
+}
+
+/// Serializes an amount with the same precision used for account balances,
+/// always rounding half to even. Only account output follows a configured
+/// precision and [`RoundingMode`], see [`WithPrecision`].
+pub fn serialize_amount<S>(amount: &Decimal, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format_amount(
+        *amount,
+        DECIMAL_PLACES,
+        RoundingMode::default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn converts_amounts_to_minor_units() {
+        assert_eq!(
+            to_minor_units(dec!(1.2345), DECIMAL_PLACES, RoundingMode::HalfEven),
+            12345
+        );
+        assert_eq!(
+            to_minor_units(dec!(1.5), DECIMAL_PLACES, RoundingMode::HalfEven),
+            15000
+        );
+        assert_eq!(
+            to_minor_units(dec!(-0.0001), DECIMAL_PLACES, RoundingMode::HalfEven),
+            -1
+        );
+        assert_eq!(
+            to_minor_units(Decimal::ZERO, DECIMAL_PLACES, RoundingMode::HalfEven),
+            0
+        );
+        assert_eq!(
+            to_minor_units(dec!(1.23455), 2, RoundingMode::HalfEven),
+            123
+        );
+    }
+
+    #[test]
+    fn formats_amounts_at_configured_precision() {
+        assert_eq!(
+            format_amount(dec!(1.23455), 2, RoundingMode::HalfEven),
+            "1.23"
+        );
+        assert_eq!(
+            format_amount(dec!(1.23455), 4, RoundingMode::HalfEven),
+            "1.2346"
+        );
+        assert_eq!(
+            format_amount(dec!(1.23445), 4, RoundingMode::HalfEven),
+            "1.2344"
+        );
+        assert_eq!(
+            format_amount(dec!(1.23455), 8, RoundingMode::HalfEven),
+            "1.23455000"
+        );
+        assert_eq!(
+            format_amount(dec!(1.225), 2, RoundingMode::HalfEven),
+            "1.22"
+        );
+        assert_eq!(format_amount(dec!(2.5), 0, RoundingMode::HalfEven), "2");
+        assert_eq!(format_amount(dec!(-0.5), 0, RoundingMode::HalfEven), "0");
+    }
+
+    #[test]
+    fn formats_midpoints_with_each_rounding_mode() {
+        let cases = [
+            (RoundingMode::HalfEven, "2.0000", "-2.0000"),
+            (RoundingMode::HalfUp, "2.0001", "-2.0001"),
+            (RoundingMode::TowardZero, "2.0000", "-2.0000"),
+        ];
+
+        for (rounding, positive, negative) in cases {
+            assert_eq!(format_amount(dec!(2.00005), 4, rounding), positive);
+            assert_eq!(format_amount(dec!(-2.00005), 4, rounding), negative);
+        }
+
+        assert_eq!(
+            format_amount(dec!(2.00015), 4, RoundingMode::HalfEven),
+            "2.0002"
+        );
+        assert_eq!(
+            format_amount(dec!(2.00019), 4, RoundingMode::TowardZero),
+            "2.0001"
+        );
+        assert_eq!(
+            to_minor_units(dec!(-2.00005), DECIMAL_PLACES, RoundingMode::HalfUp),
+            -20001
+        );
+    }
+
+    #[test]
+    fn empty_accounts_are_unlocked_without_funds() {
+        let funded = Account {
+            available: dec!(1.0),
+            total: dec!(1.0),
+            ..Account::new(1)
+        };
+        let locked = Account {
+            locked: true,
+            ..Account::new(2)
+        };
+
+        assert!(Account::new(3).is_empty());
+        assert!(!funded.is_empty());
+        assert!(!locked.is_empty());
+    }
+
+    #[test]
+    fn invariant_requires_total_to_match_balances() {
+        let mut acct = Account {
+            available: dec!(6.0),
+            held: dec!(4.0),
+            total: dec!(10.0),
+            ..Account::new(1)
+        };
+
+        assert!(acct.check_invariant());
+
+        acct.held += dec!(4.0);
+
+        assert!(!acct.check_invariant());
+    }
+}
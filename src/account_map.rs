@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::ClientId;
+use crate::account::Account;
+
+/// Accounts storage keyed by client id.
+///
+/// Optionally keeps the most recently used accounts in a small array in
+/// front of the map, lookups on those clients compare ids instead of hashing
+/// them. This pays off for skewed workloads where a few clients receive most
+/// of the transactions, but costs an extra map operation on every miss.
+///
+/// Clients below a configured id can also be stored in a vector indexed by
+/// id, which suits inputs with dense client ids. Higher ids fall back to the
+/// map.
+pub struct AccountMap {
+    map: HashMap<ClientId, Account>,
+    /// Accounts of clients below `dense.len()`, indexed by client id
+    dense: Vec<Option<(ClientId, Account)>>,
+    /// Most recently used accounts, most recent first
+    hot: Vec<(ClientId, Account)>,
+    hot_capacity: usize,
+}
+
+impl AccountMap {
+    /// Creates an empty map caching up to `hot_capacity` accounts, zero
+    /// disables the cache.
+    #[cfg(test)]
+    pub fn new(hot_capacity: usize) -> Self {
+        Self::with_dense(hot_capacity, 0)
+    }
+
+    /// Creates an empty map storing clients with ids below `dense_clients`
+    /// in a vector, zero disables the vector.
+    pub fn with_dense(hot_capacity: usize, dense_clients: usize) -> Self {
+        let dense_clients = dense_clients.min(ClientId::MAX as usize + 1);
+
+        AccountMap {
+            map: HashMap::new(),
+            dense: vec![None; dense_clients],
+            hot: Vec::with_capacity(hot_capacity),
+            hot_capacity,
+        }
+    }
+
+    pub fn get(&self, client: &ClientId) -> Option<&Account> {
+        if let Some(slot) = self.dense.get(*client as usize) {
+            return slot.as_ref().map(|(_, acct)| acct);
+        }
+
+        self.hot
+            .iter()
+            .find(|(id, _)| id == client)
+            .map(|(_, acct)| acct)
+            .or_else(|| self.map.get(client))
+    }
+
+    pub fn get_mut(&mut self, client: &ClientId) -> Option<&mut Account> {
+        if self.is_dense(*client) {
+            return self.dense[*client as usize].as_mut().map(|(_, acct)| acct);
+        }
+
+        if self.hot_capacity == 0 {
+            return self.map.get_mut(client);
+        }
+
+        if let Some(pos) = self.hot.iter().position(|(id, _)| id == client) {
+            self.hot[..=pos].rotate_right(1);
+            return Some(&mut self.hot[0].1);
+        }
+
+        let acct = self.map.remove(client)?;
+
+        Some(self.promote(*client, acct))
+    }
+
+    pub fn get_or_insert_with<F>(&mut self, client: ClientId, f: F) -> &mut Account
+    where
+        F: FnOnce() -> Account,
+    {
+        if self.is_dense(client) {
+            return &mut self.dense[client as usize]
+                .get_or_insert_with(|| (client, f()))
+                .1;
+        }
+
+        if self.hot_capacity == 0 {
+            return self.map.entry(client).or_insert_with(f);
+        }
+
+        if self.get_mut(&client).is_none() {
+            self.promote(client, f());
+        }
+
+        &mut self.hot[0].1
+    }
+
+    pub fn insert(&mut self, client: ClientId, acct: Account) {
+        if let Some(slot) = self.dense.get_mut(client as usize) {
+            *slot = Some((client, acct));
+            return;
+        }
+
+        match self.hot.iter_mut().find(|(id, _)| *id == client) {
+            Some((_, cached)) => *cached = acct,
+            None => {
+                self.map.insert(client, acct);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, client: &ClientId) -> Option<Account> {
+        if let Some(slot) = self.dense.get_mut(*client as usize) {
+            return slot.take().map(|(_, acct)| acct);
+        }
+
+        match self.hot.iter().position(|(id, _)| id == client) {
+            Some(pos) => Some(self.hot.remove(pos).1),
+            None => self.map.remove(client),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ClientId, &Account)> {
+        self.dense
+            .iter()
+            .flatten()
+            .chain(self.hot.iter())
+            .map(|(id, acct)| (id, acct))
+            .chain(self.map.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &ClientId> {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = (ClientId, Account)> {
+        self.dense
+            .drain(..)
+            .flatten()
+            .chain(self.hot.drain(..))
+            .chain(self.map.drain())
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty() && self.hot.is_empty() && self.dense.iter().all(Option::is_none)
+    }
+
+    fn is_dense(&self, client: ClientId) -> bool {
+        (client as usize) < self.dense.len()
+    }
+
+    /// Places the account at the front of the hot set, evicting the least
+    /// recently used one back into the map when full.
+    fn promote(&mut self, client: ClientId, acct: Account) -> &mut Account {
+        if self.hot.len() == self.hot_capacity
+            && let Some((id, evicted)) = self.hot.pop()
+        {
+            self.map.insert(id, evicted);
+        }
+
+        self.hot.insert(0, (client, acct));
+        &mut self.hot[0].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_into_map() {
+        let mut accounts = AccountMap::new(2);
+
+        for id in 1..=3 {
+            accounts.get_or_insert_with(id, || Account::new(id));
+        }
+
+        // Touching 1 brings it back in, evicting 2
+        accounts.get_mut(&1).expect("account 1").locked = true;
+
+        let hot: Vec<ClientId> = accounts.hot.iter().map(|(id, _)| *id).collect();
+        let mut all: Vec<ClientId> = accounts.keys().copied().collect();
+        all.sort_unstable();
+
+        assert_eq!(hot, vec![1, 3]);
+        assert_eq!(all, vec![1, 2, 3]);
+        assert!(accounts.get(&1).is_some_and(|acct| acct.locked));
+    }
+
+    #[test]
+    fn dense_ids_fall_back_to_map_when_out_of_range() {
+        let mut accounts = AccountMap::with_dense(0, 4);
+
+        for id in [0, 3, 4, 1000] {
+            accounts.get_or_insert_with(id, || Account::new(id));
+        }
+
+        let dense: Vec<ClientId> = accounts.dense.iter().flatten().map(|(id, _)| *id).collect();
+        let mut all: Vec<ClientId> = accounts.keys().copied().collect();
+        all.sort_unstable();
+
+        assert_eq!(dense, vec![0, 3]);
+        assert_eq!(all, vec![0, 3, 4, 1000]);
+        assert!(accounts.remove(&3).is_some());
+        assert!(accounts.get(&3).is_none());
+        assert_eq!(accounts.drain().count(), 3);
+        assert!(accounts.is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use rust_decimal::Decimal;
+
+use txs::ClientId;
+use txs::config::{FirstWithdrawalPolicy, LockPolicy, RoundingMode};
+use txs::csv_io::{InputFormat, QuotePolicy, TrimPolicy};
+use txs::output::{OutputFormat, SortKey};
+
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// File receiving error and warning logs, `-` writes them to stderr
+    #[arg(
+        long,
+        global = true,
+        env = "TXS_ERROR_LOG",
+        value_name = "PATH",
+        default_value = "error.log"
+    )]
+    pub error_log: PathBuf,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Process two transaction files independently and report the per
+    /// client balance differences of the second against the first
+    Diff(DiffArgs),
+    /// Compare two transaction files row by row and report the
+    /// transactions only one of them has or that differ between them
+    Reconcile(ReconcileArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Paths to the CSV files with transactions to process, applied one
+    /// after the other to the same ledger. `-` reads stdin
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Path to a CSV file with opening balances, in the output format
+    #[arg(long)]
+    pub opening: Option<PathBuf>,
+    /// Resume from a ledger snapshot written by `--snapshot` instead of
+    /// starting empty. The snapshot already holds the opening balances
+    #[arg(long, value_name = "PATH", conflicts_with = "opening")]
+    pub resume: Option<PathBuf>,
+    /// Write a JSON snapshot of the ledger after processing, to continue
+    /// from with `--resume`
+    #[arg(long, value_name = "PATH")]
+    pub snapshot: Option<PathBuf>,
+    /// Only write accounts whose final state differs from the opening
+    /// balances
+    #[arg(long, requires = "opening")]
+    pub only_changed: bool,
+    /// Only process transactions for these clients (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub allow_clients: Option<Vec<ClientId>>,
+    /// Skip transactions for these clients (comma separated), takes
+    /// precedence over `--allow-clients`
+    #[arg(long, value_delimiter = ',')]
+    pub deny_clients: Vec<ClientId>,
+    /// Fail if a client is both allowed and denied
+    #[arg(long)]
+    pub strict_filters: bool,
+    /// Lowest available balance a withdrawal may leave in an account
+    #[arg(long, default_value_t = Decimal::ZERO)]
+    pub min_balance: Decimal,
+    /// Flat fee debited on every withdrawal on top of its amount
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        conflicts_with = "withdrawal_fee_percent",
+        value_parser = parse_non_negative
+    )]
+    pub withdrawal_fee: Option<Decimal>,
+    /// Fee debited on every withdrawal as a percentage of its amount
+    #[arg(long, value_name = "PERCENT", value_parser = parse_non_negative)]
+    pub withdrawal_fee_percent: Option<Decimal>,
+    /// How far below zero withdrawals may take the available balance
+    #[arg(long, default_value_t = Decimal::ZERO)]
+    pub overdraft_limit: Decimal,
+    /// Reject deposits and withdrawals above this amount
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_amount: Option<Decimal>,
+    /// Reject transactions on accounts holding more funds than their total,
+    /// which only inconsistent opening balances can produce
+    #[arg(long)]
+    pub check_held: bool,
+    /// Check `total == available + held` before every transaction,
+    /// rejecting it when an account it touches breaks the invariant
+    #[arg(long)]
+    pub check_invariants: bool,
+    /// Reject support rows with a non-empty amount, partial disputes as well
+    /// as resolves and chargebacks whose amount is otherwise ignored
+    #[arg(long)]
+    pub strict_support: bool,
+    /// Keep timestamped deposits held for this many seconds before they
+    /// become available
+    #[arg(long, value_name = "SECONDS")]
+    pub deposit_hold: Option<u32>,
+    /// Reject rows timestamped before an earlier row with `OutOfOrder`
+    #[arg(long)]
+    pub reject_out_of_order: bool,
+    /// Resolve open disputes once this many more transactions were applied
+    #[arg(long, value_name = "COUNT", conflicts_with = "dispute_expiry_secs")]
+    pub dispute_expiry_txs: Option<u64>,
+    /// Resolve open disputes once the input timestamps are this many seconds
+    /// past them
+    #[arg(long, value_name = "SECONDS")]
+    pub dispute_expiry_secs: Option<u32>,
+    /// Abort once more than this many rows could not be read or were
+    /// rejected, by default every error is logged and processing continues
+    #[arg(long, value_name = "COUNT")]
+    pub max_errors: Option<usize>,
+    /// Fail the run on the first row that cannot be read or is rejected
+    #[arg(long)]
+    pub strict: bool,
+    /// Handling of a withdrawal arriving before any deposit of the client
+    #[arg(long, value_enum, default_value_t)]
+    pub first_withdrawal: FirstWithdrawalPolicy,
+    /// What accounts locked by a chargeback still accept
+    #[arg(long, value_enum, default_value_t)]
+    pub lock_policy: LockPolicy,
+    /// Reject disputes on accounts that already have this many open
+    #[arg(long, value_name = "COUNT")]
+    pub max_open_disputes: Option<u32>,
+    /// Keep this many most recently used accounts in a lookup cache, useful
+    /// when a few clients dominate the input
+    #[arg(long, default_value_t = 0)]
+    pub hot_accounts: usize,
+    /// Store clients with an id below this value in a vector indexed by id,
+    /// faster than the default map when client ids are dense
+    #[arg(long, default_value_t = 0)]
+    pub dense_accounts: usize,
+    /// Apply transactions in `effective_date` order instead of input order,
+    /// this buffers the whole input in memory
+    #[arg(long)]
+    pub effective_order: bool,
+    /// Whitespace trimming applied to the input CSV
+    #[arg(long, value_enum, default_value_t)]
+    pub trim: TrimPolicy,
+    /// Format of the input files
+    #[arg(long, value_enum, default_value_t)]
+    pub input_format: InputFormat,
+    /// Field delimiter of the input, a single ASCII character
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_delimiter)]
+    pub delimiter: u8,
+    /// Read tab separated input
+    #[arg(long, conflicts_with = "delimiter")]
+    pub tsv: bool,
+    /// Match the input header row regardless of case
+    #[arg(long)]
+    pub ignore_header_case: bool,
+    /// Format used to write the accounts summary
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Write the accounts summary to this file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// Leave out unlocked accounts without funds
+    #[arg(long)]
+    pub skip_empty: bool,
+    /// Order of the output accounts, by default they are ordered by client
+    /// id
+    #[arg(long, value_enum)]
+    pub sort_by: Option<SortKey>,
+    /// Terminate CSV output records with `\r\n` instead of `\n`
+    #[arg(long)]
+    pub crlf: bool,
+    /// Leave the header row out of CSV output
+    #[arg(long)]
+    pub no_header: bool,
+    /// Which fields of CSV output are quoted
+    #[arg(long, value_enum, default_value_t)]
+    pub quote_style: QuotePolicy,
+    /// Write balances as integers in the smallest unit (scaled by
+    /// 10^precision) instead of decimal strings
+    #[arg(long)]
+    pub minor_units: bool,
+    /// Decimal places of the output balances, rounded with `--rounding`
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(0..=28))]
+    pub precision: u32,
+    /// Rounding of the output balances and of percentage withdrawal fees.
+    /// Event, risk report and diff amounts are always rounded half to even
+    #[arg(long, value_enum, default_value_t)]
+    pub rounding: RoundingMode,
+    /// Start CSV output with a `#` comment line stating the amounts unit
+    #[arg(long)]
+    pub annotate_units: bool,
+    /// Check every account against a replay of its transactions after
+    /// processing, failing the run when they differ
+    #[arg(long)]
+    pub verify_replay: bool,
+    /// Process the input without writing accounts or events, print the
+    /// processing report to stderr and exit with 2 if any transaction would
+    /// not apply
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Only read the input and print an estimate of the memory processing
+    /// it would take, nothing is applied
+    #[arg(long)]
+    pub estimate_memory: bool,
+    /// Write every state change applied by the ledger as newline-delimited
+    /// JSON events
+    #[arg(long, value_name = "PATH")]
+    pub events: Option<PathBuf>,
+    /// Write a JSON report of held funds, open disputes and locks per client
+    #[arg(long)]
+    pub risk_report: Option<PathBuf>,
+    /// Write the ids of every locked account, in the `--format` of the
+    /// accounts output
+    #[arg(long, value_name = "PATH")]
+    pub locked_report: Option<PathBuf>,
+    /// Write a JSON report of applied and rejected transactions per type and
+    /// error, `-` prints it to stderr
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+}
+
+/// Fees are debited, a negative one would credit the account instead.
+fn parse_non_negative(value: &str) -> Result<Decimal, String> {
+    match value.parse::<Decimal>() {
+        Ok(amount) if amount.is_sign_negative() && !amount.is_zero() => {
+            Err(format!("expected a non-negative amount, got `{value}`"))
+        }
+        Ok(amount) => Ok(amount),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [delimiter] => Ok(*delimiter),
+        _ => Err(format!("expected a single ASCII character, got `{value}`")),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Path to the baseline CSV file with transactions
+    pub before: PathBuf,
+    /// Path to the CSV file with transactions compared against the baseline
+    pub after: PathBuf,
+    /// Format used to write the differences
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct ReconcileArgs {
+    /// Path to the first CSV file with transactions
+    pub left: PathBuf,
+    /// Path to the second CSV file with transactions
+    pub right: PathBuf,
+    /// Format used to write the differences
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
@@ -0,0 +1,167 @@
+use chrono::Duration;
+use clap::ValueEnum;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::account::DECIMAL_PLACES;
+
+/// How a withdrawal is handled when the client has no account yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FirstWithdrawalPolicy {
+    /// Reject it with `AccountNotFound`
+    #[default]
+    Reject,
+    /// Open an empty account and reject the withdrawal with
+    /// `InsufficientFunds`
+    OpenThenFail,
+}
+
+/// Age past which an open dispute is resolved by
+/// [`Ledger::expire_disputes`](crate::ledger::Ledger::expire_disputes).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisputeExpiry {
+    /// Once this many transactions were applied after the dispute
+    Transactions(u64),
+    /// Once the input timestamps are this far past the dispute, disputes
+    /// opened before any timestamp was seen never expire
+    Elapsed(Duration),
+}
+
+/// Fee charged on every withdrawal on top of the withdrawn amount.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeePolicy {
+    #[default]
+    None,
+    /// The same amount on every withdrawal
+    Flat(Decimal),
+    /// A percentage of the withdrawn amount, e.g. `1.5` for 1.5%
+    Percentage(Decimal),
+}
+
+impl FeePolicy {
+    /// Fee due on a withdrawal of `amount`, rounded to the account precision
+    /// with `rounding`. `None` when a percentage fee overflows.
+    pub fn fee(self, amount: Decimal, rounding: RoundingMode) -> Option<Decimal> {
+        match self {
+            FeePolicy::None => Some(Decimal::ZERO),
+            FeePolicy::Flat(fee) => Some(fee),
+            FeePolicy::Percentage(percent) => amount
+                .checked_mul(percent)
+                .and_then(|fee| fee.checked_div(Decimal::ONE_HUNDRED))
+                .map(|fee| fee.round_dp_with_strategy(DECIMAL_PLACES, rounding.strategy())),
+        }
+    }
+}
+
+/// How amounts are rounded when written and when a computed amount, e.g. a
+/// percentage fee, has more decimal places than the account precision.
+/// Midpoints of negative amounts round like their absolute value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RoundingMode {
+    /// Midpoints round to the even neighbour, also known as banker's rounding
+    #[default]
+    HalfEven,
+    /// Midpoints round away from zero
+    HalfUp,
+    /// Every amount is truncated toward zero
+    TowardZero,
+}
+
+impl RoundingMode {
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::TowardZero => RoundingStrategy::ToZero,
+        }
+    }
+}
+
+/// What a locked account still accepts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LockPolicy {
+    /// Reject every transaction on a locked account
+    #[default]
+    FreezeAll,
+    /// Keep crediting deposits and incoming transfers, everything else is
+    /// still rejected with `LockedAccount`
+    BlockWithdrawalsOnly,
+}
+
+impl LockPolicy {
+    /// Whether deposits into a locked account are rejected.
+    pub fn freezes_deposits(self) -> bool {
+        self == LockPolicy::FreezeAll
+    }
+}
+
+/// Business rules applied by the [`Ledger`](crate::ledger::Ledger) while
+/// processing transactions. The default configuration matches the behavior
+/// of a plain ledger.
+#[derive(Clone, Debug, Default)]
+pub struct LedgerConfig {
+    /// Lowest `available` balance a withdrawal is allowed to leave behind
+    pub min_balance: Decimal,
+    /// Fee debited from the account on every withdrawal
+    pub withdrawal_fee: FeePolicy,
+    /// Rounding of computed amounts, percentage fees and held interest
+    pub rounding: RoundingMode,
+    /// Interest accrued per period on the funds held by open disputes, as a
+    /// fraction, e.g. `0.01` for 1%. Accrued by
+    /// [`Ledger::accrue_interest`](crate::ledger::Ledger::accrue_interest),
+    /// which refuses a negative rate
+    pub held_interest_rate: Decimal,
+    /// Largest amount a single deposit or withdrawal may carry, larger ones
+    /// are rejected with `AmountExceedsLimit` as likely corrupt data
+    pub max_amount: Option<Decimal>,
+    /// How far below zero withdrawals and outgoing transfers may take
+    /// `available` (and with it `total`). The minimum balance is lowered by
+    /// the same amount.
+    pub overdraft_limit: Decimal,
+    /// Number of most recently used accounts kept in front of the accounts
+    /// map, zero disables the cache. Only worth enabling when a handful of
+    /// clients receive most of the transactions.
+    pub hot_accounts: usize,
+    /// Clients with an id below this value are stored in a vector indexed
+    /// by id instead of the accounts map, zero disables it. Suits inputs
+    /// where client ids are dense.
+    pub dense_accounts: usize,
+    /// Reject transactions on accounts holding more than their total plus
+    /// the overdraft limit. The transaction handlers never produce such an
+    /// account, but opening balances with a negative `available` can.
+    pub check_held: bool,
+    /// Verify `total == available + held` on the accounts a transaction
+    /// touches before applying it, rejecting it with `BrokenInvariant` when
+    /// it does not hold. Meant to catch handler bugs, a broken account is
+    /// reported by the next transaction on it, at the cost of a check per
+    /// transaction.
+    pub check_invariants: bool,
+    /// Reject support transactions carrying an amount: partial disputes are
+    /// turned off and an amount on a resolve or chargeback, otherwise
+    /// ignored, is refused instead
+    pub strict_support: bool,
+    /// Highest number of disputes a single account may have open at once,
+    /// further disputes are rejected until one is resolved or charged back
+    pub max_open_disputes_per_account: Option<u32>,
+    /// Time deposits stay in `held` before settling into `available`, only
+    /// deposits carrying a timestamp are held. Settled amounts are released
+    /// by the next transaction of the account past the hold.
+    pub deposit_hold: Option<Duration>,
+    /// What accounts locked by a chargeback still accept
+    pub lock_policy: LockPolicy,
+    /// Reject transactions timestamped before the latest timestamp seen so
+    /// far with `OutOfOrder`, transactions without a timestamp are accepted
+    pub reject_out_of_order: bool,
+    /// Age past which open disputes are resolved automatically
+    pub dispute_expiry: Option<DisputeExpiry>,
+    /// Expire disputes after every applied transaction instead of only on
+    /// explicit `expire_disputes` calls
+    pub auto_expire_disputes: bool,
+    /// Handling of a withdrawal arriving before any deposit of the client
+    pub first_tx_withdrawal_policy: FirstWithdrawalPolicy,
+    /// Abort processing once more than this many transactions could not
+    /// be read or were rejected, `None` never aborts
+    pub max_errors: Option<usize>,
+    /// Abort processing on the first transaction that could not be read or
+    /// was rejected, returning its error
+    pub strict: bool,
+}
@@ -1,62 +1,396 @@
-use std::io::stdout;
+use std::io::{Read, Write, stdin};
 use std::path::Path;
 use std::task::{Context, Poll};
 use std::{fs::File, pin::Pin};
 
 use anyhow::Result;
-use csv::{ReaderBuilder, Trim};
+use clap::ValueEnum;
+use csv::{
+    DeserializeRecordsIntoIter, QuoteStyle, ReaderBuilder, StringRecord, Terminator, Trim,
+    WriterBuilder,
+};
 use futures::Stream;
 
-use crate::{account::Account, tx::Transaction};
+use crate::account::{Account, DECIMAL_PLACES, MinorUnits, WithPrecision};
+use crate::config::RoundingMode;
+use crate::error::Error;
+use crate::output::AccountWriter;
+use crate::tx::Transaction;
 
+/// Columns every transaction input must have, in any order.
+const REQUIRED_HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Whitespace trimming applied to CSV fields, mirrors [`csv::Trim`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TrimPolicy {
+    /// Keep whitespace untouched
+    None,
+    /// Trim whitespace in the header row only
+    Headers,
+    /// Trim whitespace in record fields only
+    Fields,
+    /// Trim whitespace in headers and record fields
+    #[default]
+    All,
+}
+
+impl From<TrimPolicy> for Trim {
+    fn from(policy: TrimPolicy) -> Self {
+        match policy {
+            TrimPolicy::None => Trim::None,
+            TrimPolicy::Headers => Trim::Headers,
+            TrimPolicy::Fields => Trim::Fields,
+            TrimPolicy::All => Trim::All,
+        }
+    }
+}
+
+/// Format of the transactions input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Comma separated values with a header row, see [`CsvReader`]
+    #[default]
+    Csv,
+    /// A JSON array of transaction objects, see
+    /// [`JsonReader`](crate::json_io::JsonReader)
+    Json,
+}
+
+/// Quoting of CSV output fields, mirrors [`csv::QuoteStyle`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum QuotePolicy {
+    /// Quote every field
+    Always,
+    /// Quote fields only when they contain a delimiter, quote or line
+    /// break
+    #[default]
+    Necessary,
+    /// Never quote fields, even when the output can no longer be read back
+    Never,
+}
+
+impl From<QuotePolicy> for QuoteStyle {
+    fn from(policy: QuotePolicy) -> Self {
+        match policy {
+            QuotePolicy::Always => QuoteStyle::Always,
+            QuotePolicy::Necessary => QuoteStyle::Necessary,
+            QuotePolicy::Never => QuoteStyle::Never,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CsvReaderOptions {
+    pub trim: TrimPolicy,
+    /// Field delimiter, defaults to `,`
+    pub delimiter: u8,
+    /// Match the header row regardless of case, e.g. `Type` or `TX`
+    pub ignore_header_case: bool,
+}
+
+impl Default for CsvReaderOptions {
+    fn default() -> Self {
+        CsvReaderOptions {
+            trim: TrimPolicy::default(),
+            delimiter: b',',
+            ignore_header_case: false,
+        }
+    }
+}
+
+/// Streams the transactions of a CSV file, one record per poll.
+///
+/// The deserializing iterator lives as long as the reader, so its record
+/// buffer is reused across polls.
 pub struct CsvReader {
-    reader: csv::Reader<File>,
+    records: DeserializeRecordsIntoIter<Box<dyn Read + Send>, Transaction>,
 }
 
 impl CsvReader {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let reader = ReaderBuilder::new()
-            .trim(Trim::All)
-            .from_path(path)
-            .expect("Failed to build CSV reader");
+        Self::with_options(path, CsvReaderOptions::default())
+    }
+
+    /// Opens the CSV file at `path`, files with a `.zst` or `.gz` extension
+    /// are decompressed while reading when built with the `zstd` or `gzip`
+    /// feature respectively. A path of `-` reads from stdin.
+    pub fn with_options<P: AsRef<Path>>(path: P, options: CsvReaderOptions) -> Result<Self> {
+        Self::from_reader(open_input(path.as_ref())?, options)
+    }
+
+    /// Reads CSV transactions from any source, e.g. stdin or a buffer. A
+    /// leading UTF-8 byte order mark, as written by some Windows tools, is
+    /// skipped.
+    ///
+    /// The header row is read right away and fails with `CsvHeader` when
+    /// any of the `type`, `client`, `tx` or `amount` columns is missing.
+    /// Records are deserialized by column name, so extra and reordered
+    /// columns are fine. An empty input has no header and reads no records.
+    pub fn from_reader<R>(input: R, options: CsvReaderOptions) -> Result<Self>
+    where
+        R: Read + Send + 'static,
+    {
+        let input: Box<dyn Read + Send> = Box::new(input);
+        let mut reader = ReaderBuilder::new()
+            .trim(options.trim.into())
+            .delimiter(options.delimiter)
+            .from_reader(input);
+
+        // A header row that cannot be read fails again on the first record
+        if let Ok(headers) = reader.headers() {
+            let headers: StringRecord = if options.ignore_header_case {
+                headers.iter().map(str::to_lowercase).collect()
+            } else {
+                headers.clone()
+            };
+
+            check_headers(&headers)?;
+            reader.set_headers(headers);
+        }
 
-        Ok(CsvReader { reader })
+        Ok(CsvReader {
+            records: reader.into_deserialize(),
+        })
     }
 }
 
+/// Opens an input file, decompressing `.zst` and `.gz` files when built
+/// with the matching feature. A path of `-` reads from stdin.
+pub(crate) fn open_input(path: &Path) -> Result<Box<dyn Read + Send>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(stdin()));
+    }
+
+    let file = File::open(path)?;
+    let input: Box<dyn Read + Send> = match path.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "zstd")]
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => anyhow::bail!("Reading zstd input requires the `zstd` feature"),
+        #[cfg(feature = "gzip")]
+        Some("gz") => Box::new(flate2::read::GzDecoder::new(file)),
+        #[cfg(not(feature = "gzip"))]
+        Some("gz") => anyhow::bail!("Reading gzip input requires the `gzip` feature"),
+        _ => Box::new(file),
+    };
+
+    Ok(input)
+}
+
+fn check_headers(headers: &StringRecord) -> Result<()> {
+    if headers.is_empty() {
+        return Ok(());
+    }
+
+    let missing: Vec<String> = REQUIRED_HEADERS
+        .into_iter()
+        .filter(|required| !headers.iter().any(|header| header == *required))
+        .map(String::from)
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::CsvHeader {
+        missing,
+        found: headers.iter().map(String::from).collect(),
+    }
+    .into())
+}
+
+/// Reads opening balances with the same columns produced by [`CsvWriter`].
+pub fn read_accounts<P: AsRef<Path>>(path: P) -> Result<Vec<Account>> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+    let accounts = reader
+        .deserialize()
+        .collect::<csv::Result<Vec<Account>>>()?;
+
+    Ok(accounts)
+}
+
 impl Stream for CsvReader {
     type Item = Result<Transaction>;
 
     fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut iter = self.get_mut().reader.deserialize();
+        let records = &mut self.get_mut().records;
+        // The reader stops right past the previous record, on the line of the
+        // record about to be read
+        let record_line = records.reader().position().line();
+        let next = records.next().map(|result| {
+            let tx = result.map_err(|source| {
+                // I/O errors carry no position, the reader is still close
+                // to the failing record
+                let line = source
+                    .position()
+                    .unwrap_or_else(|| records.reader().position())
+                    .line();
+
+                Error::CsvParse { line, source }
+            })?;
 
-        match iter.next() {
-            Some(result) => Poll::Ready(Some(result.map_err(|e| e.into()))),
-            None => Poll::Ready(None),
+            tx.require_amount()
+                .map_err(|err| anyhow::Error::from(err).context(format!("line {record_line}")))
+        });
+
+        Poll::Ready(next)
+    }
+}
+
+impl CsvReader {
+    /// Groups transactions into batches of at most `size` elements.
+    ///
+    /// Only a single batch is kept in memory at a time, the last batch may
+    /// hold fewer than `size` transactions.
+    pub fn chunks(self, size: usize) -> CsvChunks {
+        assert!(size > 0, "Chunk size must be greater than zero");
+
+        CsvChunks {
+            reader: self,
+            size,
+            pending_err: None,
         }
     }
 }
 
-pub struct CsvWriter {
-    writer: csv::Writer<std::io::Stdout>,
+pub struct CsvChunks {
+    reader: CsvReader,
+    size: usize,
+    pending_err: Option<anyhow::Error>,
 }
 
-impl CsvWriter {
-    pub fn new() -> Result<Self> {
-        let writer = csv::Writer::from_writer(stdout());
+impl Stream for CsvChunks {
+    type Item = Result<Vec<Transaction>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Some(err) = this.pending_err.take() {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        let mut chunk = Vec::with_capacity(this.size);
 
-        Ok(CsvWriter { writer })
+        while chunk.len() < this.size {
+            match Pin::new(&mut this.reader).poll_next(cx) {
+                Poll::Ready(Some(Ok(tx))) => chunk.push(tx),
+                Poll::Ready(Some(Err(err))) => {
+                    if chunk.is_empty() {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+
+                    // Yield the transactions read so far, the error is
+                    // surfaced on the next poll.
+                    this.pending_err = Some(err);
+                    break;
+                }
+                Poll::Ready(None) => break,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        if chunk.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        Poll::Ready(Some(Ok(chunk)))
     }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CsvWriterOptions {
+    /// Record terminator, defaults to `\n`
+    pub terminator: Terminator,
+    /// Write balances as integers in the smallest unit
+    pub minor_units: bool,
+    /// Start the output with a `#` comment line stating the amounts unit
+    pub annotate_units: bool,
+    /// Decimal places balances are rounded to
+    pub precision: u32,
+    /// How balances are rounded to `precision`, half to even by default
+    pub rounding: RoundingMode,
+    /// Start the output with the header row
+    pub has_headers: bool,
+    /// Which fields are quoted
+    pub quote_style: QuotePolicy,
+}
+
+impl Default for CsvWriterOptions {
+    fn default() -> Self {
+        CsvWriterOptions {
+            terminator: Terminator::Any(b'\n'),
+            minor_units: false,
+            annotate_units: false,
+            precision: DECIMAL_PLACES,
+            rounding: RoundingMode::default(),
+            has_headers: true,
+            quote_style: QuotePolicy::default(),
+        }
+    }
+}
+
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    minor_units: bool,
+    precision: u32,
+    rounding: RoundingMode,
+    /// Annotation still to be written ahead of the first record
+    annotation: Option<String>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn from_writer(writer: W) -> Self {
+        Self::with_options(writer, CsvWriterOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: CsvWriterOptions) -> Self {
+        let writer = WriterBuilder::new()
+            .terminator(options.terminator)
+            .has_headers(options.has_headers)
+            .quote_style(options.quote_style.into())
+            .flexible(options.annotate_units)
+            .from_writer(writer);
+
+        let precision = options.precision;
+        let annotation = match (options.annotate_units, options.minor_units) {
+            (false, _) => None,
+            (true, false) => Some(format!(
+                "# amounts in units with {precision} decimal places"
+            )),
+            (true, true) => Some(format!("# amounts in 1e-{precision} units")),
+        };
+
+        CsvWriter {
+            writer,
+            minor_units: options.minor_units,
+            precision,
+            rounding: options.rounding,
+            annotation,
+        }
+    }
+}
+
+impl<W: Write> AccountWriter for CsvWriter<W> {
+    fn write(&mut self, record: &Account) -> Result<()> {
+        // The annotation is a single field record, the writer is flexible
+        // when annotating so it does not clash with the header length.
+        if let Some(annotation) = self.annotation.take() {
+            self.writer.write_record([annotation])?;
+        }
+
+        if self.minor_units {
+            self.writer
+                .serialize(MinorUnits(record, self.precision, self.rounding))?;
+        } else {
+            self.writer
+                .serialize(WithPrecision(record, self.precision, self.rounding))?;
+        }
 
-    pub fn write(&mut self, record: &Account) -> Result<()> {
-        self.writer
-            .serialize(record)
-            .expect("Failed to serialize account record");
         Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<()> {
-        self.writer.flush().expect("Failed to flush writer");
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
         Ok(())
     }
 }
@@ -64,9 +398,33 @@ impl CsvWriter {
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
-    use futures::TryStreamExt;
+    use chrono::NaiveDate;
+    use futures::{StreamExt, TryStreamExt};
+    use rust_decimal::Decimal;
 
     use super::*;
+    use crate::TransactionId;
+    use crate::output::{ClosedPipe, is_broken_pipe};
+
+    #[tokio::test]
+    async fn parse_errors_carry_the_line_number() -> Result<()> {
+        let results: Vec<_> = CsvReader::new("fixtures/malformed_row.csv")?
+            .collect()
+            .await;
+        let err = results
+            .iter()
+            .find_map(|result| result.as_ref().err())
+            .expect("expected a parse error");
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::CsvParse { line: 4, .. })
+        ));
+        assert!(format!("{err:#}").starts_with("line 4: "));
+
+        Ok(())
+    }
 
     #[tokio::test]
     async fn reads_from_csv_file() -> Result<()> {
@@ -78,4 +436,507 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn writer_reports_broken_pipe_without_panicking() {
+        let mut writer = CsvWriter::from_writer(ClosedPipe);
+
+        let result = writer
+            .write(&Account::default())
+            .and_then(|_| writer.flush());
+
+        let err = result.expect_err("expected broken pipe error");
+
+        assert!(is_broken_pipe(&err));
+    }
+
+    #[test]
+    fn writer_uses_crlf_terminator_when_configured() -> Result<()> {
+        let options = CsvWriterOptions {
+            terminator: Terminator::CRLF,
+            ..CsvWriterOptions::default()
+        };
+        let mut buf = Vec::new();
+        let mut writer = CsvWriter::with_options(&mut buf, options);
+
+        writer.write(&Account::new(0))?;
+        writer.flush()?;
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "client,available,held,total,locked\r\n0,0.0000,0.0000,0.0000,false\r\n"
+        );
+
+        let mut buf = Vec::new();
+        let mut writer = CsvWriter::from_writer(&mut buf);
+
+        writer.write(&Account::default())?;
+        writer.flush()?;
+        drop(writer);
+
+        assert!(!String::from_utf8(buf)?.contains('\r'));
+
+        Ok(())
+    }
+
+    #[test]
+    fn writer_outputs_minor_units_when_configured() -> Result<()> {
+        let options = CsvWriterOptions {
+            minor_units: true,
+            ..CsvWriterOptions::default()
+        };
+        let mut buf = Vec::new();
+        let mut writer = CsvWriter::with_options(&mut buf, options);
+
+        writer.write(&Account {
+            id: Some(1),
+            available: Decimal::new(12345, 4),
+            held: Decimal::new(5, 1),
+            total: Decimal::new(17345, 4),
+            locked: false,
+        })?;
+        writer.flush()?;
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "client,available,held,total,locked\n1,12345,5000,17345,false\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn writer_rounds_to_configured_precision() -> Result<()> {
+        let account = Account {
+            available: Decimal::new(123455, 5),
+            held: Decimal::ZERO,
+            total: Decimal::new(123455, 5),
+            ..Account::new(1)
+        };
+        let write = |precision| -> Result<String> {
+            let mut buf = Vec::new();
+            let mut writer = CsvWriter::with_options(
+                &mut buf,
+                CsvWriterOptions {
+                    precision,
+                    ..CsvWriterOptions::default()
+                },
+            );
+            writer.write(&account)?;
+            writer.flush()?;
+            drop(writer);
+
+            Ok(String::from_utf8(buf)?)
+        };
+
+        assert_eq!(
+            write(2)?,
+            "client,available,held,total,locked\n1,1.23,0.00,1.23,false\n"
+        );
+        assert_eq!(
+            write(8)?,
+            "client,available,held,total,locked\n1,1.23455000,0.00000000,1.23455000,false\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn writer_controls_header_and_quoting() -> Result<()> {
+        let write = |options: CsvWriterOptions| -> Result<String> {
+            let mut buf = Vec::new();
+            let mut writer = CsvWriter::with_options(&mut buf, options);
+
+            writer.write(&Account {
+                available: Decimal::new(15, 1),
+                total: Decimal::new(15, 1),
+                ..Account::new(1)
+            })?;
+            writer.flush()?;
+            drop(writer);
+
+            Ok(String::from_utf8(buf)?)
+        };
+
+        assert_eq!(
+            write(CsvWriterOptions::default())?,
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+        );
+        assert_eq!(
+            write(CsvWriterOptions {
+                has_headers: false,
+                ..CsvWriterOptions::default()
+            })?,
+            "1,1.5000,0.0000,1.5000,false\n"
+        );
+        assert_eq!(
+            write(CsvWriterOptions {
+                quote_style: QuotePolicy::Always,
+                ..CsvWriterOptions::default()
+            })?,
+            "\"client\",\"available\",\"held\",\"total\",\"locked\"\n\
+             \"1\",\"1.5000\",\"0.0000\",\"1.5000\",\"false\"\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn writer_annotates_units_only_when_enabled() -> Result<()> {
+        let write = |options: CsvWriterOptions| -> Result<String> {
+            let mut buf = Vec::new();
+            let mut writer = CsvWriter::with_options(&mut buf, options);
+
+            writer.write(&Account {
+                id: Some(1),
+                available: Decimal::new(12345, 4),
+                ..Account::default()
+            })?;
+            writer.flush()?;
+            drop(writer);
+
+            Ok(String::from_utf8(buf)?)
+        };
+
+        let plain = write(CsvWriterOptions {
+            minor_units: true,
+            ..CsvWriterOptions::default()
+        })?;
+
+        assert!(plain.starts_with("client,"));
+
+        let annotated = write(CsvWriterOptions {
+            minor_units: true,
+            annotate_units: true,
+            ..CsvWriterOptions::default()
+        })?;
+
+        assert!(annotated.starts_with("# amounts in 1e-4 units\nclient,"));
+
+        let mut reader = ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .from_reader(annotated.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().collect::<csv::Result<_>>()?;
+
+        assert_eq!(
+            reader.headers()?,
+            &vec!["client", "available", "held", "total", "locked"]
+        );
+        assert_eq!(rows.len(), 1);
+        assert_eq!(&rows[0][1], "12345");
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_accounts_from_csv_file() -> Result<()> {
+        let accounts = read_accounts("fixtures/opening_01.csv")?;
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[1].id, Some(2));
+        assert!(accounts[1].locked);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn trim_policy_controls_field_padding() -> Result<()> {
+        let options = CsvReaderOptions {
+            trim: TrimPolicy::None,
+            ..CsvReaderOptions::default()
+        };
+        let reader = CsvReader::with_options("fixtures/padded_fields.csv", options)?;
+        let txs: Vec<Result<Transaction>> = reader.into_stream().collect().await;
+
+        // The padded amount is kept verbatim and is not a valid decimal
+        assert!(txs[0].is_err());
+
+        let options = CsvReaderOptions {
+            trim: TrimPolicy::All,
+            ..CsvReaderOptions::default()
+        };
+        let reader = CsvReader::with_options("fixtures/padded_fields.csv", options)?;
+        let txs: Vec<Transaction> = reader.into_stream().try_collect().await?;
+
+        assert_eq!(txs[0].amount, Some(Decimal::new(15, 1)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delimiter_option_reads_tab_and_semicolon_files() -> Result<()> {
+        let expected: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+
+        for (path, delimiter) in [
+            ("fixtures/sample_01.tsv", b'\t'),
+            ("fixtures/sample_01_semicolon.csv", b';'),
+        ] {
+            let options = CsvReaderOptions {
+                delimiter,
+                ..CsvReaderOptions::default()
+            };
+            let txs: Vec<Transaction> = CsvReader::with_options(path, options)?
+                .into_stream()
+                .try_collect()
+                .await?;
+
+            assert_eq!(txs, expected, "{path}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_byte_order_mark() -> Result<()> {
+        let expected: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+        let txs: Vec<Transaction> = CsvReader::new("fixtures/sample_01_bom.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+
+        assert_eq!(txs.len(), 5);
+        assert_eq!(txs, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn header_case_is_ignored_when_configured() -> Result<()> {
+        let input = "\u{feff}Type,Client,TX,Amount\ndeposit,1,1,1.0\n";
+        let options = CsvReaderOptions {
+            ignore_header_case: true,
+            ..CsvReaderOptions::default()
+        };
+        let txs: Vec<Transaction> = CsvReader::from_reader(input.as_bytes(), options)?
+            .into_stream()
+            .try_collect()
+            .await?;
+
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].amount, Some(Decimal::ONE));
+
+        let reader = CsvReader::from_reader(input.as_bytes(), CsvReaderOptions::default());
+
+        assert!(reader.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_amount_is_only_accepted_on_support_rows() -> Result<()> {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 2.0\n\
+                     dispute, 1, 1,\n\
+                     deposit, 1, 2,\n\
+                     withdrawal, 1, 3, \n\
+                     resolve, 1, 1,\n";
+        let txs: Vec<Result<Transaction>> =
+            CsvReader::from_reader(input.as_bytes(), CsvReaderOptions::default())?
+                .collect()
+                .await;
+
+        assert_eq!(txs.len(), 5);
+        assert!(matches!(&txs[1], Ok(tx) if tx.amount.is_none()));
+        assert!(txs[4].is_ok());
+
+        for (err, line) in [(&txs[2], 4), (&txs[3], 5)] {
+            let err = err.as_ref().expect_err("domestic row without amount");
+
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::DomesticTransactionMissingAmount { tx: _ })
+            ));
+            assert!(format!("{err:#}").starts_with(&format!("line {line}: ")));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_header_missing_required_columns() {
+        let input = "client, tx, amount\n1, 1, 1.0\n";
+        let result = CsvReader::from_reader(input.as_bytes(), CsvReaderOptions::default());
+
+        match result.err().map(|err| err.downcast::<Error>()) {
+            Some(Ok(Error::CsvHeader { missing, found })) => {
+                assert_eq!(missing, ["type"]);
+                assert_eq!(found, ["client", "tx", "amount"]);
+            }
+            other => panic!("expected a CsvHeader error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_reordered_header_by_name() -> Result<()> {
+        let input = "amount, memo, tx, type, client\n2.5, rent, 7, deposit, 3\n";
+        let txs: Vec<Transaction> =
+            CsvReader::from_reader(input.as_bytes(), CsvReaderOptions::default())?
+                .into_stream()
+                .try_collect()
+                .await?;
+
+        assert_eq!(txs.len(), 1);
+        assert_eq!((txs[0].client, txs[0].id), (3, 7));
+        assert_eq!(txs[0].amount, Some(Decimal::new(25, 1)));
+        assert_eq!(txs[0].memo.as_deref(), Some("rent"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_optional_effective_date() -> Result<()> {
+        let reader = CsvReader::new("fixtures/effective_dates.csv")?;
+        let txs: Vec<Transaction> = reader.into_stream().try_collect().await?;
+
+        assert_eq!(txs[0].effective, NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!(txs[2].effective, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_optional_memo() -> Result<()> {
+        let reader = CsvReader::new("fixtures/memos.csv")?;
+        let txs: Vec<Transaction> = reader.into_stream().try_collect().await?;
+
+        assert_eq!(txs[0].memo.as_deref(), Some("payroll"));
+        assert_eq!(txs[1].memo, None);
+        assert_eq!(
+            txs[2].memo.as_deref(),
+            Some("card reported stolen, ticket 4411")
+        );
+
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 10.0\n";
+        let txs: Vec<Transaction> =
+            CsvReader::from_reader(input.as_bytes(), CsvReaderOptions::default())?
+                .into_stream()
+                .try_collect()
+                .await?;
+
+        assert_eq!(txs[0].memo, None);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wide-tx-ids")]
+    #[tokio::test]
+    async fn reads_transaction_ids_above_u32() -> Result<()> {
+        let reader = CsvReader::new("fixtures/wide_tx_ids.csv")?;
+        let txs: Vec<Transaction> = reader.into_stream().try_collect().await?;
+
+        assert_eq!(txs[0].id, u32::MAX as u64 + 1);
+
+        let mut ledger = crate::ledger::Ledger::new();
+        let summary = ledger
+            .process_transactions(futures::stream::iter(txs.into_iter().map(Ok)))
+            .await?;
+
+        assert_eq!(summary.applied, 3);
+        assert_eq!(summary.rejected, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_from_in_memory_reader_like_a_file() -> Result<()> {
+        let input = std::fs::read("fixtures/sample_01.csv")?;
+
+        let from_file: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+        let from_reader: Vec<Transaction> =
+            CsvReader::from_reader(std::io::Cursor::new(input), CsvReaderOptions::default())?
+                .into_stream()
+                .try_collect()
+                .await?;
+
+        assert_eq!(from_reader, from_file);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_every_row_of_a_large_file() -> Result<()> {
+        const ROWS: usize = 100_000;
+
+        let path = std::env::temp_dir().join(format!("txs-large-{}.csv", std::process::id()));
+        let mut input = String::from("type,client,tx,amount\n");
+
+        for id in 1..=ROWS {
+            input.push_str(&format!("deposit,{},{id},{}.5\n", id % 7, id % 100));
+        }
+
+        std::fs::write(&path, input)?;
+
+        let expected: Vec<Transaction> = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(&path)?
+            .deserialize()
+            .collect::<csv::Result<_>>()?;
+        let txs: Result<Vec<Transaction>> =
+            CsvReader::new(&path)?.into_stream().try_collect().await;
+        std::fs::remove_file(&path)?;
+        let txs = txs?;
+
+        assert_eq!(txs.len(), ROWS);
+        assert_eq!(txs, expected);
+        assert_eq!(txs[ROWS - 1].id, ROWS as TransactionId);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn reads_zstd_compressed_csv_file() -> Result<()> {
+        let plain: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+        let compressed: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv.zst")?
+            .into_stream()
+            .try_collect()
+            .await?;
+
+        assert_eq!(compressed, plain);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn reads_gzip_compressed_csv_file() -> Result<()> {
+        let plain: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .into_stream()
+            .try_collect()
+            .await?;
+        let compressed: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv.gz")?
+            .into_stream()
+            .try_collect()
+            .await?;
+
+        assert_eq!(compressed.len(), 5);
+        assert_eq!(compressed, plain);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_from_csv_file_in_chunks() -> Result<()> {
+        let reader = CsvReader::new("fixtures/sample_01.csv")?;
+        let chunks: Vec<Vec<Transaction>> = reader.chunks(2).try_collect().await?;
+        let sizes: Vec<usize> = chunks.iter().map(Vec::len).collect();
+
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        Ok(())
+    }
 }
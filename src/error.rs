@@ -1,10 +1,13 @@
+use rust_decimal::Decimal;
+use strum::IntoStaticStr;
 use thiserror::Error;
 
+use crate::ClientId;
 use crate::tx::Transaction;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, IntoStaticStr)]
 pub enum Error {
     #[error("Account not found for client: {tx:?}")]
     AccountNotFound { tx: Transaction },
@@ -12,16 +15,86 @@ pub enum Error {
     InsufficientFunds { tx: Transaction },
     #[error("Transaction not found: {tx:?}")]
     TransactionNotFound { tx: Transaction },
+    #[error("Withdrawals cannot be disputed: {tx:?}")]
+    WithdrawalNotDisputable { tx: Transaction },
     #[error("Dispute Transaction not found: {tx:?}. No dispute in progress.")]
     DisputeTxNotFound { tx: Transaction },
+    #[error("Transaction is already under dispute: {tx:?}")]
+    AlreadyDisputed { tx: Transaction },
+    #[error("Transaction was charged back and cannot be disputed again: {tx:?}")]
+    TransactionClosed { tx: Transaction },
+    #[error("Only charged back transactions can be reversed: {tx:?}")]
+    NotChargedBack { tx: Transaction },
+    #[error("Withdrawal would leave the account below its minimum balance: {tx:?}")]
+    BelowMinimumBalance { tx: Transaction },
+    #[error("Account has reached its limit of open disputes: {tx:?}")]
+    TooManyOpenDisputes { tx: Transaction },
     #[error("Account locked. Transaction cannot be pocessed: {tx:?}")]
     LockedAccount { tx: Transaction },
     #[error("Account has inconsistent held funds: {tx:?}")]
     IncosistentHeldFunds { tx: Transaction },
+    #[error("Account total no longer matches available + held after: {tx:?}")]
+    BrokenInvariant { tx: Transaction },
     #[error("Domestic transaction is missing amount invalid: {tx:?}")]
     DomesticTransactionMissingAmount { tx: Transaction },
-    #[error("Negative transaction amount: {tx:?}")]
-    NegativeAmount { tx: Transaction },
+    #[error("Support transaction carries an amount: {tx:?}")]
+    SupportTransactionWithAmount { tx: Transaction },
+    #[error("Transaction amount must be greater than zero: {tx:?}")]
+    InvalidAmount { tx: Transaction },
+    #[error("Transaction amount exceeds the limit of {limit}: {tx:?}")]
+    AmountExceedsLimit { tx: Transaction, limit: Decimal },
+    #[error("Storage failed: {reason}")]
+    Storage { reason: String },
+    #[error("Transaction amount overflows the account balance: {tx:?}")]
+    AmountOverflow { tx: Transaction },
+    #[error("Disputed amount exceeds the original deposit: {tx:?}")]
+    DisputeExceedsDeposit { tx: Transaction },
+    #[error("Transfer needs a destination client other than its source: {tx:?}")]
+    InvalidTransfer { tx: Transaction },
+    #[error("Transaction is timestamped before the previous one: {tx:?}")]
+    OutOfOrder { tx: Transaction },
     #[error("Duplicate Transaction: {tx:?}")]
     DuplicateTransaction { tx: Transaction },
+    #[error("Clients are both allowed and denied by the client filter: {clients:?}")]
+    ContradictoryClientFilter { clients: Vec<ClientId> },
+    #[error("Opening balances where total differs from available + held for clients: {clients:?}")]
+    InconsistentOpeningBalances { clients: Vec<ClientId> },
+    #[error("Opening balance is not bound to a client")]
+    UnboundOpeningBalance,
+    #[error("No account found for client: {client}")]
+    UnknownClient { client: ClientId },
+    #[error("Account cannot be closed while funded, disputed or locked, client: {client}")]
+    AccountNotClosable { client: ClientId },
+    /// `client` is `None` when the collected fees overflow
+    #[error("Merged amounts overflow, client: {client:?}")]
+    MergeOverflow { client: Option<ClientId> },
+    #[error("Held interest rate cannot be negative: {rate}")]
+    InvalidInterestRate { rate: Decimal },
+    #[error("line {line}")]
+    CsvParse { line: u64, source: csv::Error },
+    #[error("element {index}")]
+    JsonParse {
+        index: usize,
+        source: serde_json::Error,
+    },
+    #[error("CSV header is missing columns {missing:?}, found {found:?}")]
+    CsvHeader {
+        missing: Vec<String>,
+        found: Vec<String>,
+    },
+    #[error("Transaction could not be read: {reason}")]
+    UnreadableTransaction { reason: String },
+    #[error("Aborted after {errors} read or processing errors, the input is too corrupt to trust")]
+    TooManyErrors { errors: usize },
+    #[error("Dry run found {errors} transactions that would not apply")]
+    DryRunRejected { errors: usize },
+    #[error("Accounts differ from the replay of their transactions for clients: {clients:?}")]
+    ReplayMismatch { clients: Vec<ClientId> },
+}
+
+impl Error {
+    /// Name of the variant, used to tally errors by kind.
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
 }
@@ -0,0 +1,200 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tracing::error;
+
+use crate::account::serialize_amount;
+use crate::{ClientId, TransactionId};
+
+/// State change applied by the ledger, emitted in the order changes happen
+/// so downstream systems can rebuild account state from them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    Deposited {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    Withdrawn {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Fee debited alongside the withdrawal `tx`
+    FeeCharged {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Funds moved from the account of `from` to the account of `to`
+    Transferred {
+        from: ClientId,
+        to: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Signed administrative correction of `available` and `total`
+    Adjusted {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Funds moved from `available` into `held`
+    FundsHeld {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Funds moved from `held` back into `available`
+    FundsReleased {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Deposits whose settlement hold elapsed became available
+    DepositsSettled {
+        client: ClientId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Held funds removed from the account
+    ChargedBack {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    AccountLocked {
+        client: ClientId,
+    },
+    /// Interest accrued on the funds of an open dispute, held with them
+    InterestAccrued {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// Charged back funds credited back to the account
+    ChargebackReversed {
+        client: ClientId,
+        tx: TransactionId,
+        #[serde(serialize_with = "serialize_amount")]
+        amount: Decimal,
+    },
+    /// The last chargeback of a locked account was reversed
+    AccountUnlocked {
+        client: ClientId,
+    },
+}
+
+/// Destination of the events emitted by the ledger, sinks are `Send + Sync`
+/// so a ledger can be moved to or shared with other threads.
+pub trait EventSink: Send + Sync {
+    fn emit(&mut self, event: LedgerEvent);
+}
+
+/// Event line as written by [`NdjsonEventSink`].
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+    #[serde(flatten)]
+    event: &'a LedgerEvent,
+}
+
+/// Writes events as newline-delimited JSON. Write failures are logged, a
+/// broken event stream does not stop processing.
+pub struct NdjsonEventSink<W: Write> {
+    writer: W,
+    run_id: Option<String>,
+}
+
+impl<W: Write> NdjsonEventSink<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonEventSink {
+            writer,
+            run_id: None,
+        }
+    }
+
+    /// Tags every event with the id of the run producing it.
+    pub fn run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+}
+
+impl<W: Write + Send + Sync> EventSink for NdjsonEventSink<W> {
+    fn emit(&mut self, event: LedgerEvent) {
+        let record = EventRecord {
+            run_id: self.run_id.as_deref(),
+            event: &event,
+        };
+        let result = serde_json::to_writer(&mut self.writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+
+        if let Err(e) = result {
+            error!("Error writing ledger event {:?}: {:?}", event, e);
+        }
+    }
+}
+
+impl<W: Write> Drop for NdjsonEventSink<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            error!("Error flushing ledger events: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn every_event_carries_the_run_id() {
+        let mut out = Vec::new();
+
+        {
+            let mut sink = NdjsonEventSink::new(&mut out).run_id("run-1");
+            sink.emit(LedgerEvent::Deposited {
+                client: 1,
+                tx: 1,
+                amount: dec!(2),
+            });
+            sink.emit(LedgerEvent::AccountLocked { client: 1 });
+        }
+
+        let lines: Vec<Value> = String::from_utf8(out)
+            .expect("utf-8 output")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("valid json"))
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line["run_id"] == "run-1"));
+        assert_eq!(lines[0]["event"], "deposited");
+        assert_eq!(lines[1]["event"], "account_locked");
+    }
+
+    #[test]
+    fn run_id_is_omitted_when_unset() {
+        let mut out = Vec::new();
+        NdjsonEventSink::new(&mut out).emit(LedgerEvent::AccountLocked { client: 1 });
+
+        assert_eq!(out, b"{\"event\":\"account_locked\",\"client\":1}\n");
+    }
+}
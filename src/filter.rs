@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use crate::ClientId;
+use crate::error::{Error, Result};
+
+/// Restricts processing to a subset of clients.
+///
+/// When a client is present in both the allowlist and the denylist the
+/// denylist takes precedence, unless the filter is built in strict mode in
+/// which case the overlap is reported as a configuration error.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter {
+    allow: Option<HashSet<ClientId>>,
+    deny: HashSet<ClientId>,
+}
+
+impl ClientFilter {
+    pub fn new(allow: Option<Vec<ClientId>>, deny: Vec<ClientId>, strict: bool) -> Result<Self> {
+        let allow: Option<HashSet<ClientId>> = allow.map(|ids| ids.into_iter().collect());
+        let deny: HashSet<ClientId> = deny.into_iter().collect();
+
+        if strict && let Some(allow) = &allow {
+            let mut clients: Vec<ClientId> = allow.intersection(&deny).copied().collect();
+
+            if !clients.is_empty() {
+                clients.sort_unstable();
+                return Err(Error::ContradictoryClientFilter { clients });
+            }
+        }
+
+        Ok(Self { allow, deny })
+    }
+
+    pub fn is_allowed(&self, client: ClientId) -> bool {
+        if self.deny.contains(&client) {
+            return false;
+        }
+
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.contains(&client))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_every_client_by_default() {
+        let filter = ClientFilter::default();
+
+        assert!(filter.is_allowed(1));
+        assert!(filter.is_allowed(ClientId::MAX));
+    }
+
+    #[test]
+    fn allowlist_and_denylist_are_applied() -> Result<()> {
+        let filter = ClientFilter::new(Some(vec![1, 2]), vec![3], false)?;
+
+        assert!(filter.is_allowed(1));
+        assert!(filter.is_allowed(2));
+        assert!(!filter.is_allowed(3));
+        assert!(!filter.is_allowed(4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn denylist_wins_on_overlap_when_lenient() -> Result<()> {
+        let filter = ClientFilter::new(Some(vec![1, 2]), vec![2], false)?;
+
+        assert!(filter.is_allowed(1));
+        assert!(!filter.is_allowed(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn overlap_is_rejected_when_strict() {
+        let result = ClientFilter::new(Some(vec![1, 2, 3]), vec![3, 2], true);
+
+        assert!(matches!(
+            result,
+            Err(Error::ContradictoryClientFilter { clients }) if clients == vec![2, 3]
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use futures::Stream;
+use serde_json::Value;
+
+use crate::account::{Account, DECIMAL_PLACES, MinorUnits, WithPrecision};
+use crate::config::RoundingMode;
+use crate::csv_io::open_input;
+use crate::error::Error;
+use crate::output::AccountWriter;
+use crate::tx::Transaction;
+
+/// Streams the transactions of a JSON array of transaction objects, e.g.
+/// `[{"type": "deposit", "client": 1, "tx": 1, "amount": "10.0"}]`.
+///
+/// Objects have the fields of the CSV columns and deserialize into the same
+/// [`Transaction`], amounts may be strings or numbers. The array is parsed
+/// upfront, so a malformed document fails when opening it, while an object
+/// that is not a valid transaction only fails its own element.
+///
+/// Parsing upfront keeps every element of the document in memory until it
+/// is read, unlike [`CsvReader`](crate::csv_io::CsvReader) which holds one
+/// record at a time. Large inputs are better served as CSV.
+pub struct JsonReader {
+    elements: std::vec::IntoIter<Value>,
+    /// Position of the next element in the array
+    index: usize,
+}
+
+impl JsonReader {
+    /// Opens the JSON file at `path`, compressed files and `-` for stdin are
+    /// handled like [`CsvReader::with_options`](crate::csv_io::CsvReader::with_options).
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_reader(open_input(path.as_ref())?)
+    }
+
+    pub fn from_reader<R: Read>(input: R) -> Result<Self> {
+        let elements: Vec<Value> = serde_json::from_reader(BufReader::new(input))?;
+
+        Ok(JsonReader {
+            elements: elements.into_iter(),
+            index: 0,
+        })
+    }
+}
+
+impl Stream for JsonReader {
+    type Item = Result<Transaction>;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let next = this.elements.next().map(|element| {
+            let index = this.index;
+            this.index += 1;
+
+            let tx: Transaction = serde_json::from_value(element)
+                .map_err(|source| Error::JsonParse { index, source })?;
+
+            tx.require_amount()
+                .map_err(|err| anyhow::Error::from(err).context(format!("element {index}")))
+        });
+
+        Poll::Ready(next)
+    }
+}
+
+/// Writes accounts as newline-delimited JSON, one object per line.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    minor_units: bool,
+    precision: u32,
+    rounding: RoundingMode,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter {
+            writer,
+            minor_units: false,
+            precision: DECIMAL_PLACES,
+            rounding: RoundingMode::default(),
+        }
+    }
+
+    /// Decimal places balances are rounded to, half to even unless
+    /// configured otherwise with [`rounding`](Self::rounding).
+    pub fn precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// How balances are rounded to the precision.
+    pub fn rounding(mut self, rounding: RoundingMode) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Writes balances as integers in the smallest unit instead of decimal
+    /// strings.
+    pub fn minor_units(mut self, enabled: bool) -> Self {
+        self.minor_units = enabled;
+        self
+    }
+}
+
+impl<W: Write> AccountWriter for NdjsonWriter<W> {
+    fn write(&mut self, record: &Account) -> Result<()> {
+        if self.minor_units {
+            let record = MinorUnits(record, self.precision, self.rounding);
+            serde_json::to_writer(&mut self.writer, &record)?;
+        } else {
+            let record = WithPrecision(record, self.precision, self.rounding);
+            serde_json::to_writer(&mut self.writer, &record)?;
+        }
+
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, TryStreamExt};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::csv_io::CsvReader;
+    use crate::output::{ClosedPipe, is_broken_pipe};
+
+    #[tokio::test]
+    async fn reads_same_transactions_as_csv() -> Result<()> {
+        let from_json: Vec<Transaction> = JsonReader::new("fixtures/sample_01.json")?
+            .try_collect()
+            .await?;
+        let from_csv: Vec<Transaction> = CsvReader::new("fixtures/sample_01.csv")?
+            .try_collect()
+            .await?;
+
+        assert_eq!(from_json, from_csv);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_element_fails_on_its_own() -> Result<()> {
+        let input = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": 1.5},
+            {"type": "refund", "client": 1, "tx": 2, "amount": "1.0"},
+            {"type": "dispute", "client": 1, "tx": 1},
+            {"type": "withdrawal", "client": 1, "tx": 3}
+        ]"#;
+        let txs: Vec<Result<Transaction>> =
+            JsonReader::from_reader(input.as_bytes())?.collect().await;
+
+        assert_eq!(txs.len(), 4);
+        assert_eq!(
+            txs[0].as_ref().ok().and_then(|tx| tx.amount),
+            Some(dec!(1.5))
+        );
+        assert!(matches!(
+            txs[1].as_ref().map_err(|err| err.downcast_ref::<Error>()),
+            Err(Some(Error::JsonParse { index: 1, .. }))
+        ));
+        assert!(txs[2].is_ok());
+        assert!(matches!(
+            txs[3].as_ref().map_err(|err| err.downcast_ref::<Error>()),
+            Err(Some(Error::DomesticTransactionMissingAmount { tx: _ }))
+        ));
+        assert!(JsonReader::from_reader("[{".as_bytes()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn writer_reports_broken_pipe_without_panicking() {
+        let mut writer = NdjsonWriter::new(ClosedPipe);
+
+        let err = writer
+            .write(&Account::default())
+            .expect_err("expected broken pipe error");
+
+        assert!(is_broken_pipe(&err));
+    }
+
+    #[test]
+    fn writes_one_json_object_per_line() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut writer = NdjsonWriter::new(&mut buf);
+
+        writer.write(&Account {
+            id: Some(1),
+            available: dec!(1.5),
+            held: dec!(0.0),
+            total: dec!(1.5),
+            locked: false,
+        })?;
+        writer.write(&Account {
+            id: Some(2),
+            available: dec!(0.0),
+            held: dec!(2.0),
+            total: dec!(2.0),
+            locked: true,
+        })?;
+        writer.flush()?;
+
+        let output = String::from_utf8(buf)?;
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+
+        for line in lines.iter() {
+            let value: Value = serde_json::from_str(line)?;
+            assert!(value.is_object());
+        }
+
+        let second: Value = serde_json::from_str(lines[1])?;
+
+        assert_eq!(second["client"], 2);
+        assert_eq!(second["held"], "2.0000");
+        assert_eq!(second["locked"], true);
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_exact_account_json() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut writer = NdjsonWriter::new(&mut buf);
+
+        writer.write(&Account {
+            id: Some(1),
+            available: dec!(1.5),
+            held: dec!(0),
+            total: dec!(1.5),
+            locked: false,
+        })?;
+        writer.write(&Account {
+            id: Some(7),
+            available: dec!(-0.25),
+            held: dec!(10.12345),
+            total: dec!(9.87345),
+            locked: true,
+        })?;
+        writer.flush()?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}\n\
+             {\"client\":7,\"available\":\"-0.2500\",\"held\":\"10.1234\",\"total\":\"9.8734\",\"locked\":true}\n"
+        );
+
+        Ok(())
+    }
+}
@@ -1,23 +1,607 @@
 use std::collections::{HashMap, HashSet};
 
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::error;
 
-use crate::ClientId;
-use crate::account::Account;
+use crate::account::{Account, Balance, DECIMAL_PLACES};
+use crate::account_map::AccountMap;
+use crate::config::{DisputeExpiry, FirstWithdrawalPolicy, LedgerConfig};
+use crate::csv_io::CsvReader;
 use crate::error::{Error, Result};
+use crate::event::{EventSink, LedgerEvent};
+use crate::output::AccountWriter;
+use crate::report::{BalanceDiff, MemoryEstimate, ProcessingReport, RiskEntry};
+use crate::snapshot::LedgerSnapshot;
+use crate::store::{MemoryStore, Store};
 use crate::tx::{Transaction, TransactionType};
+use crate::{ClientId, TransactionId};
 
-pub struct Ledger {
-    accounts: HashMap<ClientId, Account>,
-    tx_log: HashSet<Transaction>,
+/// Deposited amount held until its settlement time.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositHold {
+    pub release_at: DateTime<Utc>,
+    pub amount: Decimal,
+}
+
+/// Dispute status of a deposit, deposits without an entry were never
+/// disputed.
+///
+/// A deposit goes from `Disputed` to either `Resolved`, from which it can be
+/// disputed again, or `ChargedBack`, which is final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    /// Funds of the deposit are held until it is resolved or charged back
+    Disputed,
+    /// Held funds were released back to the account
+    Resolved,
+    /// Funds of the deposit were removed from the account
+    ChargedBack,
+}
+
+/// Moment a dispute was opened, to tell when it expires.
+#[derive(Clone, Copy, Debug)]
+struct DisputeOpened {
+    /// Applied transactions count, the dispute included
+    seq: u64,
+    at: Option<DateTime<Utc>>,
+}
+
+impl DisputeOpened {
+    fn has_expired(&self, expiry: DisputeExpiry, applied: u64, now: Option<DateTime<Utc>>) -> bool {
+        match expiry {
+            DisputeExpiry::Transactions(count) => applied.saturating_sub(self.seq) >= count,
+            DisputeExpiry::Elapsed(window) => {
+                self.at.zip(now).is_some_and(|(at, now)| now - at >= window)
+            }
+        }
+    }
+}
+
+/// Callback invoked with every transaction processed and its outcome.
+type ProcessedHook = Box<dyn FnMut(&Transaction, &Result<()>) + Send + Sync>;
+
+/// Applies transactions to the accounts of every client.
+///
+/// All state is kept in memory. A persistent [`Store`] additionally receives
+/// every applied transaction together with the accounts it changed.
+pub struct Ledger<S: Store = MemoryStore> {
+    accounts: AccountMap,
+    /// Applied transactions in the order they were applied, support ones
+    /// included each time they are applied
+    tx_log: Vec<Transaction>,
+    /// Client and id of every logged deposit, withdrawal, adjustment and
+    /// transfer, another one reusing them is a duplicate whatever its other
+    /// fields
+    ids: HashSet<(ClientId, TransactionId)>,
+    /// Amount of every logged deposit, the only disputable transactions, so
+    /// dispute handling does not have to scan the log
+    deposits: HashMap<(ClientId, TransactionId), Decimal>,
+    /// Fee charged by every logged withdrawal, so a replay does not depend
+    /// on the current fee policy. Also tells disputes on withdrawals apart
+    /// from disputes on unknown transactions
+    withdrawals: HashMap<(ClientId, TransactionId), Decimal>,
+    /// Dispute status of each disputed deposit
+    disputes: HashMap<(ClientId, TransactionId), DisputeState>,
+    /// Portion held by partial disputes, disputes on the whole deposit have
+    /// no entry
+    disputed_amounts: HashMap<(ClientId, TransactionId), Decimal>,
+    /// When each dispute was opened, only tracked with a dispute expiry.
+    /// Entries of disputes closed since are dropped on the next expiry.
+    dispute_opened: HashMap<(ClientId, TransactionId), DisputeOpened>,
+    /// Transactions applied so far
+    applied: u64,
+    /// Latest timestamp seen in the input
+    latest: Option<DateTime<Utc>>,
+    /// Withdrawal fees charged so far
+    fees_collected: Decimal,
+    /// Interest accrued on each open dispute, held with its funds
+    held_interest: HashMap<(ClientId, TransactionId), Decimal>,
+    /// Interest credited to each account, less the interest forfeited by
+    /// chargebacks
+    interest_accrued: HashMap<ClientId, Decimal>,
+    /// Number of disputes currently open on each account
+    open_disputes: HashMap<ClientId, u32>,
+    /// Deposits still under the settlement hold, by account
+    deposit_holds: HashMap<ClientId, Vec<DepositHold>>,
+    /// Opening balances the ledger was seeded with
+    opening: HashMap<ClientId, Account>,
+    events: Option<Box<dyn EventSink>>,
+    on_processed: Option<ProcessedHook>,
+    store: S,
+    config: LedgerConfig,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Ledger {
     pub fn new() -> Self {
+        Self::with_config(LedgerConfig::default())
+    }
+
+    pub fn with_config(config: LedgerConfig) -> Self {
+        Self::with_store(config, MemoryStore)
+    }
+
+    /// Rebuilds a ledger from a snapshot taken with [`Ledger::snapshot`].
+    pub fn restore(snapshot: LedgerSnapshot) -> Self {
+        Self::restore_with_config(snapshot, LedgerConfig::default())
+    }
+
+    pub fn restore_with_config(snapshot: LedgerSnapshot, config: LedgerConfig) -> Self {
+        Self::restore_with_store(snapshot, config, MemoryStore)
+    }
+
+    /// Reads `txs` once without applying anything and estimates the memory
+    /// processing it would take. Unreadable records are skipped.
+    pub async fn estimate_memory<T>(mut txs: T) -> MemoryEstimate
+    where
+        T: Stream<Item = anyhow::Result<Transaction>> + Unpin,
+    {
+        let mut clients = HashSet::new();
+        let mut transactions = 0;
+
+        while let Some(mb_tx) = txs.next().await {
+            if let Ok(tx) = mb_tx {
+                clients.insert(tx.client);
+                transactions += 1;
+            }
+        }
+
+        MemoryEstimate::from_counts(clients.len(), transactions)
+    }
+}
+
+impl<S: Store> Ledger<S> {
+    /// Creates a ledger writing every applied transaction and the accounts
+    /// it changed through to `store`, see [`Store`].
+    pub fn with_store(config: LedgerConfig, store: S) -> Self {
         Self {
-            accounts: HashMap::new(),
-            tx_log: HashSet::new(),
+            accounts: AccountMap::with_dense(config.hot_accounts, config.dense_accounts),
+            tx_log: Vec::new(),
+            ids: HashSet::new(),
+            deposits: HashMap::new(),
+            withdrawals: HashMap::new(),
+            disputes: HashMap::new(),
+            disputed_amounts: HashMap::new(),
+            dispute_opened: HashMap::new(),
+            applied: 0,
+            latest: None,
+            fees_collected: Decimal::ZERO,
+            held_interest: HashMap::new(),
+            interest_accrued: HashMap::new(),
+            open_disputes: HashMap::new(),
+            deposit_holds: HashMap::new(),
+            opening: HashMap::new(),
+            events: None,
+            on_processed: None,
+            store,
+            config,
+        }
+    }
+
+    /// Rebuilds a ledger from a snapshot on top of `store`. Nothing is
+    /// written to the store, it only receives what is applied from then on.
+    pub fn restore_with_store(snapshot: LedgerSnapshot, config: LedgerConfig, store: S) -> Self {
+        let mut ledger = Self::with_store(config, store);
+
+        for acct in snapshot.accounts {
+            if let Some(id) = acct.id {
+                ledger.accounts.insert(id, acct);
+            }
+        }
+
+        ledger.tx_log = snapshot.transactions;
+        ledger.ids = ledger
+            .tx_log
+            .iter()
+            .filter(|tx| !tx.r#type.is_support())
+            .map(|tx| (tx.client, tx.id))
+            .collect();
+        ledger.deposits = ledger
+            .tx_log
+            .iter()
+            .filter(|tx| matches!(tx.r#type, TransactionType::Deposit))
+            .filter_map(|tx| tx.amount.map(|amount| ((tx.client, tx.id), amount)))
+            .collect();
+        let fees: HashMap<(ClientId, TransactionId), Decimal> = snapshot
+            .withdrawal_fees
+            .into_iter()
+            .map(|(client, id, fee)| ((client, id), fee))
+            .collect();
+        ledger.withdrawals = ledger
+            .tx_log
+            .iter()
+            .filter(|tx| matches!(tx.r#type, TransactionType::Withdrawal))
+            .map(|tx| {
+                let key = (tx.client, tx.id);
+                (key, fees.get(&key).copied().unwrap_or_default())
+            })
+            .collect();
+        ledger.disputes = snapshot
+            .disputes
+            .into_iter()
+            .map(|(client, id, state)| ((client, id), state))
+            .collect();
+        ledger.disputed_amounts = snapshot
+            .disputed_amounts
+            .into_iter()
+            .map(|(client, id, amount)| ((client, id), amount))
+            .collect();
+
+        // Restored disputes age from the restore on
+        if ledger.config.dispute_expiry.is_some() {
+            ledger.dispute_opened = ledger
+                .disputes
+                .iter()
+                .filter(|(_, state)| **state == DisputeState::Disputed)
+                .map(|(key, _)| (*key, DisputeOpened { seq: 0, at: None }))
+                .collect();
+        }
+
+        ledger.open_disputes = snapshot.open_disputes;
+        ledger.deposit_holds = snapshot.deposit_holds;
+        ledger.opening = snapshot
+            .opening
+            .into_iter()
+            .filter_map(|acct| acct.id.map(|id| (id, acct)))
+            .collect();
+        ledger.fees_collected = snapshot.fees_collected;
+        ledger.held_interest = snapshot
+            .held_interest
+            .into_iter()
+            .map(|(client, id, amount)| ((client, id), amount))
+            .collect();
+        ledger.interest_accrued = snapshot.interest_accrued;
+        ledger
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Emits a [`LedgerEvent`] into `sink` for every state change applied
+    /// from now on.
+    pub fn set_event_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.events = Some(sink);
+    }
+
+    /// Calls `f` after every transaction processed from now on with the
+    /// transaction and its outcome, applied or rejected, e.g. to count them
+    /// in a metrics system. Replaces any previous callback.
+    pub fn on_processed<F>(&mut self, f: F)
+    where
+        F: FnMut(&Transaction, &Result<()>) + Send + Sync + 'static,
+    {
+        self.on_processed = Some(Box::new(f));
+    }
+
+    #[inline(always)]
+    fn emit(&mut self, event: LedgerEvent) {
+        if let Some(sink) = self.events.as_mut() {
+            sink.emit(event);
+        }
+    }
+
+    /// Captures the current state so processing can be resumed later with
+    /// [`Ledger::restore`].
+    pub fn snapshot(&self) -> LedgerSnapshot {
+        let accounts = self.accounts_summary();
+
+        let mut disputes: Vec<(ClientId, TransactionId, DisputeState)> = self
+            .disputes
+            .iter()
+            .map(|((client, id), state)| (*client, *id, *state))
+            .collect();
+        disputes.sort_unstable_by_key(|(client, id, _)| (*client, *id));
+
+        let mut disputed_amounts: Vec<(ClientId, TransactionId, Decimal)> = self
+            .disputed_amounts
+            .iter()
+            .map(|((client, id), amount)| (*client, *id, *amount))
+            .collect();
+        disputed_amounts.sort_unstable_by_key(|(client, id, _)| (*client, *id));
+
+        let mut held_interest: Vec<(ClientId, TransactionId, Decimal)> = self
+            .held_interest
+            .iter()
+            .map(|((client, id), amount)| (*client, *id, *amount))
+            .collect();
+        held_interest.sort_unstable_by_key(|(client, id, _)| (*client, *id));
+
+        let mut withdrawal_fees: Vec<(ClientId, TransactionId, Decimal)> = self
+            .withdrawals
+            .iter()
+            .filter(|(_, fee)| !fee.is_zero())
+            .map(|((client, id), fee)| (*client, *id, *fee))
+            .collect();
+        withdrawal_fees.sort_unstable_by_key(|(client, id, _)| (*client, *id));
+
+        LedgerSnapshot {
+            accounts,
+            transactions: self.tx_log.clone(),
+            disputes,
+            disputed_amounts,
+            open_disputes: self.open_disputes.clone(),
+            deposit_holds: self.deposit_holds.clone(),
+            opening: self.opening.values().cloned().collect(),
+            fees_collected: self.fees_collected,
+            held_interest,
+            interest_accrued: self.interest_accrued.clone(),
+            withdrawal_fees,
+        }
+    }
+
+    /// Verifies every opening balance satisfies `total == available + held`.
+    ///
+    /// All offending rows are reported at once so the opening file can be
+    /// fixed in a single pass.
+    pub fn apply_opening_total_check(accounts: &[Account]) -> Result<()> {
+        if accounts.iter().any(|acct| acct.id.is_none()) {
+            return Err(Error::UnboundOpeningBalance);
+        }
+
+        let clients: Vec<ClientId> = accounts
+            .iter()
+            .filter(|acct| !acct.check_invariant())
+            .filter_map(|acct| acct.id)
+            .collect();
+
+        if !clients.is_empty() {
+            return Err(Error::InconsistentOpeningBalances { clients });
+        }
+
+        Ok(())
+    }
+
+    /// Seeds the ledger with opening balances, rejecting the whole set if
+    /// any of them is inconsistent.
+    pub fn seed_opening(&mut self, accounts: Vec<Account>) -> Result<()> {
+        Self::apply_opening_total_check(&accounts)?;
+
+        for acct in accounts {
+            if let Some(id) = acct.id {
+                self.opening.insert(id, acct.clone());
+                self.accounts.insert(id, acct);
+
+                if S::PERSISTENT {
+                    self.persist_account(id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Folds `other` into this ledger, e.g. after processing several inputs
+    /// on their own threads. Balances of a client present in both ledgers
+    /// are summed, opening balances included, and the account is locked if
+    /// either was. Nothing is merged when both ledgers logged a deposit,
+    /// withdrawal or other non-support transaction with the same client and
+    /// id, or when a summed balance overflows (`MergeOverflow`).
+    pub fn merge(&mut self, mut other: Ledger<S>) -> Result<()> {
+        let conflict = other
+            .tx_log
+            .iter()
+            .find(|tx| !tx.r#type.is_support() && self.ids.contains(&(tx.client, tx.id)));
+
+        if let Some(tx) = conflict {
+            return Err(Error::DuplicateTransaction { tx: tx.clone() });
+        }
+
+        // Every sum is computed before anything is merged, so an overflow
+        // leaves this ledger untouched
+        let mut accounts = Vec::new();
+
+        for (client, acct) in other.accounts.drain() {
+            let merged = Self::add_balances(self.accounts.get(&client), &acct).ok_or(
+                Error::MergeOverflow {
+                    client: Some(client),
+                },
+            )?;
+
+            accounts.push((client, merged));
+        }
+
+        let mut opening = Vec::new();
+
+        for (client, acct) in other.opening {
+            let merged = Self::add_balances(self.opening.get(&client), &acct).ok_or(
+                Error::MergeOverflow {
+                    client: Some(client),
+                },
+            )?;
+
+            opening.push((client, merged));
+        }
+
+        let mut interest_accrued = Vec::new();
+
+        for (client, interest) in other.interest_accrued {
+            let merged = self
+                .interest_accrued
+                .get(&client)
+                .copied()
+                .unwrap_or_default()
+                .checked_add(interest)
+                .ok_or(Error::MergeOverflow {
+                    client: Some(client),
+                })?;
+
+            interest_accrued.push((client, merged));
+        }
+
+        let Some(fees_collected) = self.fees_collected.checked_add(other.fees_collected) else {
+            return Err(Error::MergeOverflow { client: None });
+        };
+
+        let clients: Vec<ClientId> = accounts.iter().map(|(client, _)| *client).collect();
+
+        for (client, acct) in accounts {
+            self.accounts.insert(client, acct);
+        }
+
+        if S::PERSISTENT {
+            for client in clients {
+                self.persist_account(client)?;
+            }
+        }
+
+        self.opening.extend(opening);
+
+        for (client, open) in other.open_disputes {
+            *self.open_disputes.entry(client).or_default() += open;
+        }
+
+        for (client, holds) in other.deposit_holds {
+            self.deposit_holds.entry(client).or_default().extend(holds);
+        }
+
+        self.tx_log.extend(other.tx_log);
+        self.ids.extend(other.ids);
+        self.deposits.extend(other.deposits);
+        self.withdrawals.extend(other.withdrawals);
+        self.disputes.extend(other.disputes);
+        self.disputed_amounts.extend(other.disputed_amounts);
+        self.dispute_opened.extend(other.dispute_opened);
+        self.fees_collected = fees_collected;
+        self.held_interest.extend(other.held_interest);
+        self.interest_accrued.extend(interest_accrued);
+
+        Ok(())
+    }
+
+    /// Removes and returns the account of `client`. Only empty accounts
+    /// without open disputes can be closed, locked accounts are kept so a
+    /// later deposit cannot reopen them unlocked. A persistent store keeps
+    /// the emptied account, it has no way to delete one.
+    pub fn close_account(&mut self, client: ClientId) -> Result<Account> {
+        let Some(account) = self.accounts.get(&client) else {
+            return Err(Error::UnknownClient { client });
+        };
+
+        let open_disputes = self.open_disputes.get(&client).copied().unwrap_or(0);
+
+        if !account.available.is_zero()
+            || !account.held.is_zero()
+            || !account.total.is_zero()
+            || account.locked
+            || open_disputes > 0
+        {
+            return Err(Error::AccountNotClosable { client });
+        }
+
+        self.open_disputes.remove(&client);
+
+        let mut account = self
+            .accounts
+            .remove(&client)
+            .expect("account was found above");
+        account.id = Some(client);
+
+        Ok(account)
+    }
+
+    /// Total of the withdrawal fees charged, see
+    /// [`LedgerConfig::withdrawal_fee`]. A [`Store`] keeps the fee of each
+    /// withdrawal, not this total, so after a restart it only counts the
+    /// fees charged since.
+    pub fn fees_collected(&self) -> Decimal {
+        self.fees_collected
+    }
+
+    /// Accrues `periods` periods of [`LedgerConfig::held_interest_rate`] on
+    /// the funds of every open dispute. The interest is simple, computed on
+    /// the disputed amount and rounded to four places with
+    /// [`LedgerConfig::rounding`], and is held along with those funds: a
+    /// resolve releases both to `available`, a chargeback removes both.
+    ///
+    /// Disputes are accrued in client and transaction id order, on overflow
+    /// the ones accrued so far are kept and `AmountOverflow` is returned
+    /// carrying the deposit that could not be accrued. A negative rate is
+    /// refused with `InvalidInterestRate` before accruing anything.
+    pub fn accrue_interest(&mut self, periods: u32) -> Result<()> {
+        let rate = self.config.held_interest_rate;
+
+        if rate.is_sign_negative() && !rate.is_zero() {
+            return Err(Error::InvalidInterestRate { rate });
+        }
+
+        let rate = rate.checked_mul(Decimal::from(periods));
+
+        if rate.is_some_and(|rate| rate.is_zero()) {
+            return Ok(());
+        }
+
+        let mut open: Vec<(ClientId, TransactionId)> = self
+            .disputes
+            .iter()
+            .filter(|(_, state)| **state == DisputeState::Disputed)
+            .map(|(key, _)| *key)
+            .collect();
+        open.sort_unstable();
+
+        for (client, id) in open {
+            let key = (client, id);
+            let principal = self
+                .disputed_amounts
+                .get(&key)
+                .or_else(|| self.deposits.get(&key))
+                .copied()
+                .unwrap_or_default();
+            let interest = rate
+                .and_then(|rate| principal.checked_mul(rate))
+                .map(|interest| {
+                    interest.round_dp_with_strategy(DECIMAL_PLACES, self.config.rounding.strategy())
+                });
+            let accrued =
+                interest
+                    .zip(self.accounts.get_mut(&client))
+                    .and_then(|(interest, account)| {
+                        let held = account.held.checked_add(interest)?;
+                        let total = account.total.checked_add(interest)?;
+
+                        account.held = held;
+                        account.total = total;
+
+                        Some(interest)
+                    });
+
+            let Some(interest) = accrued else {
+                let tx = self
+                    .find_tx(|tx| (tx.client, tx.id) == key && !tx.r#type.is_support())
+                    .cloned()
+                    .expect("open disputes refer to a logged deposit");
+
+                return Err(Error::AmountOverflow { tx });
+            };
+
+            if interest.is_zero() {
+                continue;
+            }
+
+            *self.held_interest.entry(key).or_default() += interest;
+            *self.interest_accrued.entry(client).or_default() += interest;
+
+            if S::PERSISTENT {
+                self.persist_account(client)?;
+            }
+
+            self.emit(LedgerEvent::InterestAccrued {
+                client,
+                tx: id,
+                amount: interest,
+            });
         }
+
+        Ok(())
     }
 
     pub fn find_tx<P>(&self, p: P) -> Option<&Transaction>
@@ -27,15 +611,27 @@ impl Ledger {
         self.tx_log.iter().find(p)
     }
 
+    /// Applied transactions involving `client` in the order they were
+    /// applied, including transfers it received. Rejected transactions are
+    /// not logged and never show up.
+    pub fn account_history(&self, client: ClientId) -> Vec<&Transaction> {
+        self.tx_log
+            .iter()
+            .filter(|tx| tx.client == client || tx.to == Some(client))
+            .collect()
+    }
+
     pub fn accounts_iter(&self) -> impl Iterator<Item = (&ClientId, &Account)> {
         self.accounts.iter()
     }
 
+    /// Copies of every account ordered by client id, so the same input
+    /// always produces the same output.
     pub fn accounts_summary(&self) -> Vec<Account> {
-        let accounts: Vec<Account> = self
+        let mut accounts: Vec<Account> = self
             .accounts_iter()
             .map(|(id, acct)| Account {
-                id: *id,
+                id: Some(*id),
                 available: acct.available,
                 held: acct.held,
                 locked: acct.locked,
@@ -43,52 +639,666 @@ impl Ledger {
             })
             .collect();
 
+        accounts.sort_unstable_by_key(|acct| acct.id);
         accounts
     }
 
+    /// Balances of the account of `client`, a copy so it can be queried
+    /// while transactions are still being applied.
+    pub fn balance(&self, client: ClientId) -> Option<Balance> {
+        self.accounts.get(&client).map(Balance::from)
+    }
+
+    /// Balances of every account keyed by client id.
+    pub fn balances_map(&self) -> HashMap<ClientId, Balance> {
+        self.accounts_iter()
+            .map(|(id, acct)| (*id, Balance::from(acct)))
+            .collect()
+    }
+
+    /// Writes every account while draining the ledger, so memory is released
+    /// as the output is produced instead of building a summary first.
+    /// Accounts are written in storage order rather than by client id.
+    pub fn finalize_and_stream<W>(mut self, writer: &mut W) -> anyhow::Result<()>
+    where
+        W: AccountWriter + ?Sized,
+    {
+        // Nothing can be disputed anymore, release the log before writing
+        self.tx_log = Vec::new();
+        self.ids = HashSet::new();
+        self.deposits = HashMap::new();
+        self.withdrawals = HashMap::new();
+
+        for (id, mut acct) in self.accounts.drain() {
+            acct.id = Some(id);
+            writer.write(&acct)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Ids of every locked account, sorted.
+    pub fn locked_accounts(&self) -> Vec<ClientId> {
+        let mut clients: Vec<ClientId> = self
+            .accounts_iter()
+            .filter(|(_, acct)| acct.locked)
+            .map(|(id, _)| *id)
+            .collect();
+
+        clients.sort_unstable();
+        clients
+    }
+
+    /// Builds a per client view of held funds, open disputes and lock status
+    /// sorted by client id.
+    ///
+    /// A merge can leave several charged back deposits on one client, in
+    /// which case the lowest transaction id is reported as the lock cause.
+    pub fn risk_report(&self) -> Vec<RiskEntry> {
+        let mut disputed: HashMap<ClientId, u32> = HashMap::new();
+        let mut chargebacks: HashMap<ClientId, TransactionId> = HashMap::new();
+
+        for ((client, id), state) in self.disputes.iter() {
+            match state {
+                DisputeState::Disputed => *disputed.entry(*client).or_default() += 1,
+                DisputeState::Resolved => {}
+                DisputeState::ChargedBack => {
+                    chargebacks
+                        .entry(*client)
+                        .and_modify(|lowest| *lowest = (*lowest).min(*id))
+                        .or_insert(*id);
+                }
+            }
+        }
+
+        let mut report: Vec<RiskEntry> = self
+            .accounts_iter()
+            .map(|(id, acct)| RiskEntry {
+                client: *id,
+                held: acct.held,
+                open_disputes: disputed.get(id).copied().unwrap_or_default(),
+                locked: acct.locked,
+                locked_by: acct.locked.then(|| chargebacks.get(id).copied()).flatten(),
+            })
+            .collect();
+
+        report.sort_by_key(|entry| entry.client);
+        report
+    }
+
+    /// Recomputes every account from its opening balance and the logged
+    /// transactions, returning the clients whose stored account differs,
+    /// sorted by client id.
+    ///
+    /// The log only holds applied transactions, and every effect of a
+    /// transaction is additive, so the replay does not depend on the order
+    /// transactions were applied in. Held and charged back funds follow the
+    /// current dispute state of each deposit.
+    pub fn verify_replay(&self) -> std::result::Result<(), Vec<ClientId>> {
+        let mut replayed: HashMap<ClientId, Account> = self
+            .opening
+            .iter()
+            .map(|(id, acct)| (*id, acct.clone()))
+            .collect();
+        let mut deposits: HashMap<(ClientId, TransactionId), Decimal> = HashMap::new();
+
+        for tx in self.tx_log.iter() {
+            let acct = replayed
+                .entry(tx.client)
+                .or_insert_with(|| Account::new(tx.client));
+            let amount = tx.amount.unwrap_or_default();
+
+            match tx.r#type {
+                TransactionType::Deposit => {
+                    acct.total += amount;
+                    deposits.insert((tx.client, tx.id), amount);
+                }
+                TransactionType::Withdrawal => {
+                    let fee = self.withdrawals.get(&(tx.client, tx.id)).copied();
+                    acct.total -= amount + fee.unwrap_or_default();
+                }
+                TransactionType::Adjustment => acct.total += amount,
+                TransactionType::Transfer => {
+                    acct.total -= amount;
+
+                    if let Some(to) = tx.to {
+                        replayed.entry(to).or_insert_with(|| Account::new(to)).total += amount;
+                    }
+                }
+                TransactionType::Dispute | TransactionType::Resolve => {}
+                TransactionType::Chargeback => acct.locked = true,
+                TransactionType::ChargebackReversal => {
+                    acct.locked = self.has_chargebacks(tx.client)
+                }
+            }
+        }
+
+        for (key, state) in self.disputes.iter() {
+            let deposit = match self.disputed_amounts.get(key) {
+                Some(amount) => *amount,
+                None => deposits.get(key).copied().unwrap_or_default(),
+            };
+
+            if let Some(acct) = replayed.get_mut(&key.0) {
+                match state {
+                    DisputeState::Disputed => acct.held += deposit,
+                    DisputeState::Resolved => {}
+                    DisputeState::ChargedBack => acct.total -= deposit,
+                }
+            }
+        }
+
+        for (client, holds) in self.deposit_holds.iter() {
+            if let Some(acct) = replayed.get_mut(client) {
+                acct.held += holds.iter().map(|hold| hold.amount).sum::<Decimal>();
+            }
+        }
+
+        for (client, interest) in self.interest_accrued.iter() {
+            if let Some(acct) = replayed.get_mut(client) {
+                acct.total += interest;
+            }
+        }
+
+        for ((client, _), interest) in self.held_interest.iter() {
+            if let Some(acct) = replayed.get_mut(client) {
+                acct.held += interest;
+            }
+        }
+
+        for acct in replayed.values_mut() {
+            acct.available = acct.total - acct.held;
+        }
+
+        let empty = Account::default();
+        let same = |a: &Account, b: &Account| {
+            a.available == b.available
+                && a.held == b.held
+                && a.total == b.total
+                && a.locked == b.locked
+        };
+
+        // Accounts may exist without any logged transaction, e.g. when their
+        // first deposit was rejected, and are expected to be empty.
+        let mut mismatched: Vec<ClientId> = replayed
+            .keys()
+            .chain(self.accounts.keys())
+            .copied()
+            .collect::<HashSet<ClientId>>()
+            .into_iter()
+            .filter(|id| {
+                let expected = replayed.get(id).unwrap_or(&empty);
+                let actual = self.accounts.get(id).unwrap_or(&empty);
+
+                !same(expected, actual)
+            })
+            .collect();
+
+        if mismatched.is_empty() {
+            return Ok(());
+        }
+
+        mismatched.sort_unstable();
+        Err(mismatched)
+    }
+
+    /// Compares the balances in `other` against this ledger, only clients
+    /// whose account differs are reported, sorted by client id. A client
+    /// missing from one side is compared against an empty account.
+    pub fn diff<T: Store>(&self, other: &Ledger<T>) -> Vec<BalanceDiff> {
+        let empty = Account::default();
+        let mut clients: Vec<ClientId> = self
+            .accounts
+            .keys()
+            .chain(other.accounts.keys())
+            .copied()
+            .collect::<HashSet<ClientId>>()
+            .into_iter()
+            .collect();
+
+        clients.sort_unstable();
+
+        clients
+            .into_iter()
+            .filter_map(|client| {
+                let before = self.accounts.get(&client).unwrap_or(&empty);
+                let after = other.accounts.get(&client).unwrap_or(&empty);
+                let diff = BalanceDiff {
+                    client,
+                    available: after.available - before.available,
+                    held: after.held - before.held,
+                    total: after.total - before.total,
+                    locked_before: before.locked,
+                    locked_after: after.locked,
+                };
+
+                let changed = !diff.available.is_zero()
+                    || !diff.held.is_zero()
+                    || !diff.total.is_zero()
+                    || diff.locked_before != diff.locked_after;
+
+                changed.then_some(diff)
+            })
+            .collect()
+    }
+
+    /// Summary of the accounts whose state differs from `baseline`.
+    pub fn changed_accounts<T: Store>(&self, baseline: &Ledger<T>) -> Vec<Account> {
+        let changed: HashSet<ClientId> = baseline
+            .diff(self)
+            .into_iter()
+            .map(|diff| diff.client)
+            .collect();
+
+        self.accounts_summary()
+            .into_iter()
+            .filter(|acct| acct.id.is_some_and(|id| changed.contains(&id)))
+            .collect()
+    }
+
+    /// Processes every transaction read from `reader`, errors are logged
+    /// and processing continues with the next record.
+    pub async fn process_reader(&mut self, reader: CsvReader) -> Result<ProcessingReport> {
+        self.process_transactions(reader.into_stream()).await
+    }
+
+    /// Applies the transactions in order and returns how many were applied.
+    /// With `stop_on_error` the first rejected transaction ends processing
+    /// and its error is returned along with the number applied before it,
+    /// otherwise rejections are logged and skipped.
+    pub fn apply_from_iter<I>(
+        &mut self,
+        iter: I,
+        stop_on_error: bool,
+    ) -> std::result::Result<usize, (usize, Error)>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        let mut applied = 0;
+
+        for tx in iter {
+            match self.process_tx(tx) {
+                Ok(()) => applied += 1,
+                Err(e) if stop_on_error => return Err((applied, e)),
+                Err(e) => error!("Error processing transaction {:?}", e),
+            }
+        }
+
+        Ok(applied)
+    }
+
+    /// Applies the transactions as the returned stream is polled, yielding
+    /// the outcome of each one. Nothing is logged or counted and processing
+    /// never stops on its own, dropping the stream stops it. Records that
+    /// could not be read yield [`Error::UnreadableTransaction`].
+    pub fn process_stream<T>(&mut self, txs: T) -> impl Stream<Item = Result<()>>
+    where
+        T: Stream<Item = anyhow::Result<Transaction>>,
+    {
+        txs.map(move |mb_tx| match mb_tx {
+            Ok(tx) => self.process_tx(tx),
+            Err(e) => Err(Error::UnreadableTransaction {
+                reason: format!("{e:#}"),
+            }),
+        })
+    }
+
+    /// Applies every transaction of the stream, errors are logged and
+    /// processing continues unless more than
+    /// [`LedgerConfig::max_errors`] accumulate or [`LedgerConfig::strict`]
+    /// is set.
+    pub async fn process_transactions<T>(&mut self, mut txs: T) -> Result<ProcessingReport>
+    where
+        T: Stream<Item = anyhow::Result<Transaction>> + Unpin,
+    {
+        let mut report = ProcessingReport::default();
+
+        while let Some(mb_tx) = txs.next().await {
+            match mb_tx {
+                Err(e) if self.config.strict => {
+                    return Err(Error::UnreadableTransaction {
+                        reason: format!("{e:#}"),
+                    });
+                }
+                Err(e) => {
+                    error!("Error reading transaction: {:#}", e);
+                    report.unreadable += 1;
+                    self.check_max_errors(&report)?;
+                }
+                Ok(tx) => {
+                    let r#type = tx.r#type.clone();
+                    let result = self.process_tx(tx);
+                    self.record(r#type, result, &mut report)?;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn record(
+        &self,
+        r#type: TransactionType,
+        result: Result<()>,
+        report: &mut ProcessingReport,
+    ) -> Result<()> {
+        match &result {
+            Ok(()) => {}
+            Err(_) if self.config.strict => return result,
+            Err(e) => error!("Error processing transaction {:?}", e),
+        }
+
+        report.record(r#type, &result);
+        self.check_max_errors(report)
+    }
+
+    fn check_max_errors(&self, report: &ProcessingReport) -> Result<()> {
+        let errors = report.unreadable + report.rejected;
+
+        if self.config.max_errors.is_some_and(|max| errors > max) {
+            return Err(Error::TooManyErrors { errors });
+        }
+
+        Ok(())
+    }
+
     pub fn process_tx(&mut self, tx: Transaction) -> Result<()> {
-        match tx.r#type {
+        let Some(mut on_processed) = self.on_processed.take() else {
+            return self.apply_tx(tx);
+        };
+
+        let processed = tx.clone();
+        let result = self.apply_tx(tx);
+
+        on_processed(&processed, &result);
+        self.on_processed = Some(on_processed);
+
+        result
+    }
+
+    fn apply_tx(&mut self, tx: Transaction) -> Result<()> {
+        if self.config.strict_support && tx.r#type.is_support() && tx.amount.is_some() {
+            return Err(Error::SupportTransactionWithAmount { tx });
+        }
+
+        if S::PERSISTENT {
+            self.load_from_store(&tx)?;
+        }
+
+        if self.config.check_held
+            && self
+                .accounts
+                .get(&tx.client)
+                .is_some_and(|acct| acct.held > acct.total + self.config.overdraft_limit)
+        {
+            return Err(Error::IncosistentHeldFunds { tx });
+        }
+
+        if self.config.check_invariants && self.breaks_invariant(&tx) {
+            return Err(Error::BrokenInvariant { tx });
+        }
+
+        if self.config.reject_out_of_order && tx.timestamp.is_some() && tx.timestamp < self.latest {
+            return Err(Error::OutOfOrder { tx });
+        }
+
+        if let Some(now) = tx.timestamp
+            && !self.deposit_holds.is_empty()
+        {
+            self.release_deposit_holds(tx.client, now);
+        }
+
+        if tx.timestamp > self.latest {
+            self.latest = tx.timestamp;
+        }
+
+        let persisted = S::PERSISTENT.then(|| tx.clone());
+
+        let result = match tx.r#type {
             TransactionType::Deposit => self.handle_deposit(tx),
             TransactionType::Withdrawal => self.handle_withdrawal(tx),
             TransactionType::Dispute => self.handle_dispute(tx),
             TransactionType::Resolve => self.handle_resolve(tx),
             TransactionType::Chargeback => self.handle_chargeback(tx),
+            TransactionType::ChargebackReversal => self.handle_chargeback_reversal(tx),
+            TransactionType::Adjustment => self.handle_adjustment(tx),
+            TransactionType::Transfer => self.handle_transfer(tx),
+        };
+
+        // A rejected transaction may still have opened its account
+        if let Some(tx) = persisted {
+            self.persist(&tx, result.is_ok())?;
         }
+
+        result?;
+        self.applied += 1;
+
+        if self.config.auto_expire_disputes && !self.dispute_opened.is_empty() {
+            self.expire_disputes();
+        }
+
+        Ok(())
+    }
+
+    /// Resolves every open dispute older than the configured
+    /// [`DisputeExpiry`], returning their clients and transaction ids. Each
+    /// one is logged as a resolve carrying a `dispute expired` memo. Does
+    /// nothing without an expiry.
+    pub fn expire_disputes(&mut self) -> Vec<(ClientId, TransactionId)> {
+        let Some(expiry) = self.config.dispute_expiry else {
+            return Vec::new();
+        };
+
+        let disputes = &self.disputes;
+        self.dispute_opened
+            .retain(|key, _| disputes.get(key) == Some(&DisputeState::Disputed));
+
+        let (applied, latest) = (self.applied, self.latest);
+        let mut expired: Vec<(ClientId, TransactionId)> = self
+            .dispute_opened
+            .iter()
+            .filter(|(_, opened)| opened.has_expired(expiry, applied, latest))
+            .map(|(key, _)| *key)
+            .collect();
+        expired.sort_unstable();
+
+        expired.retain(|&(client, id)| {
+            self.dispute_opened.remove(&(client, id));
+
+            let resolve = Transaction {
+                client,
+                r#type: TransactionType::Resolve,
+                id,
+                amount: None,
+                effective: None,
+                timestamp: latest,
+                memo: Some("dispute expired".to_string()),
+                to: None,
+            };
+
+            let persisted = S::PERSISTENT.then(|| resolve.clone());
+
+            match self.handle_resolve(resolve) {
+                Ok(()) => {
+                    if let Some(Err(e)) = persisted.map(|tx| self.persist(&tx, true)) {
+                        error!("Error persisting expired dispute {:?}", e);
+                    }
+
+                    true
+                }
+                Err(e) => {
+                    error!("Error expiring dispute {:?}", e);
+                    false
+                }
+            }
+        });
+
+        expired
+    }
+
+    /// Whether an account `tx` would touch does not satisfy
+    /// `total == available + held`. It is checked before applying `tx`, so
+    /// a transaction rejected with `BrokenInvariant` changed nothing.
+    #[cold]
+    fn breaks_invariant(&self, tx: &Transaction) -> bool {
+        std::iter::once(tx.client)
+            .chain(tx.to)
+            .filter_map(|client| self.accounts.get(&client))
+            .any(|acct| !acct.check_invariant())
     }
 
+    /// Deposits are the bulk of the input, so the happy path validates the
+    /// transaction with a single branch. Any failure is diagnosed in
+    /// [`Ledger::reject_deposit`].
     #[inline(always)]
     fn handle_deposit(&mut self, tx: Transaction) -> Result<()> {
-        if self.tx_log.contains(&tx) {
+        let release_at = self
+            .config
+            .deposit_hold
+            .zip(tx.timestamp)
+            .map(|(hold, timestamp)| timestamp + hold);
+        let client = tx.client;
+        let id = tx.id;
+        let freezes = self.config.lock_policy.freezes_deposits();
+        let limit = self.config.max_amount;
+        let account = self
+            .accounts
+            .get_or_insert_with(tx.client, || Account::new(tx.client));
+
+        let amount = match tx.amount {
+            Some(amount)
+                if amount > Decimal::ZERO
+                    && within(limit, amount)
+                    && !(freezes && account.locked) =>
+            {
+                amount
+            }
+            _ => return self.reject_deposit(tx),
+        };
+
+        let credited = match release_at {
+            Some(_) => account.held.checked_add(amount),
+            None => account.available.checked_add(amount),
+        };
+
+        let (Some(total), Some(credited)) = (account.total.checked_add(amount), credited) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+
+        if !self.ids.insert((client, id)) {
             return Err(Error::DuplicateTransaction { tx });
         }
 
-        let account = self.accounts.entry(tx.client).or_default();
+        self.tx_log.push(tx);
+        self.deposits.insert((client, id), amount);
+        account.total = total;
+
+        match release_at {
+            Some(release_at) => {
+                account.held = credited;
+                self.deposit_holds
+                    .entry(client)
+                    .or_default()
+                    .push(DepositHold { release_at, amount });
+            }
+            None => account.available = credited,
+        }
 
-        if account.locked {
-            return Err(Error::LockedAccount { tx });
+        if self.events.is_some() {
+            self.emit(LedgerEvent::Deposited {
+                client,
+                tx: id,
+                amount,
+            });
+
+            if release_at.is_some() {
+                self.emit(LedgerEvent::FundsHeld {
+                    client,
+                    tx: id,
+                    amount,
+                });
+            }
         }
 
-        let amount = tx.amount()?;
+        Ok(())
+    }
 
-        if amount <= Decimal::ZERO {
-            return Err(Error::NegativeAmount { tx });
+    /// Moves the deposits of `client` whose hold elapsed by `now` from
+    /// `held` into `available`.
+    fn release_deposit_holds(&mut self, client: ClientId, now: DateTime<Utc>) {
+        let Some(holds) = self.deposit_holds.get_mut(&client) else {
+            return;
+        };
+
+        let mut released = Decimal::ZERO;
+
+        holds.retain(|hold| {
+            let elapsed = hold.release_at <= now;
+
+            if elapsed {
+                released += hold.amount;
+            }
+
+            !elapsed
+        });
+
+        if holds.is_empty() {
+            self.deposit_holds.remove(&client);
         }
 
-        account.available += amount;
-        account.total += amount;
+        if let Some(account) = self.accounts.get_mut(&client) {
+            account.held -= released;
+            account.available += released;
+        }
 
-        self.tx_log.insert(tx);
+        if !released.is_zero() {
+            self.emit(LedgerEvent::DepositsSettled {
+                client,
+                amount: released,
+            });
+        }
+    }
 
-        Ok(())
+    /// Diagnoses why a deposit failed validation.
+    #[cold]
+    fn reject_deposit(&self, tx: Transaction) -> Result<()> {
+        if self.ids.contains(&(tx.client, tx.id)) {
+            return Err(Error::DuplicateTransaction { tx });
+        }
+
+        if self.config.lock_policy.freezes_deposits()
+            && self
+                .accounts
+                .get(&tx.client)
+                .is_some_and(|acct| acct.locked)
+        {
+            return Err(Error::LockedAccount { tx });
+        }
+
+        let amount = tx.amount()?;
+
+        if let Some(limit) = self.config.max_amount.filter(|limit| amount > *limit) {
+            return Err(Error::AmountExceedsLimit { tx, limit });
+        }
+
+        Err(Error::InvalidAmount { tx })
     }
 
     #[inline(always)]
     fn handle_withdrawal(&mut self, tx: Transaction) -> Result<()> {
-        if self.tx_log.contains(&tx) {
+        if self.ids.contains(&(tx.client, tx.id)) {
             return Err(Error::DuplicateTransaction { tx });
         }
 
-        let Some(account) = self.accounts.get_mut(&tx.client) else {
+        let account = match self.config.first_tx_withdrawal_policy {
+            FirstWithdrawalPolicy::Reject => self.accounts.get_mut(&tx.client),
+            FirstWithdrawalPolicy::OpenThenFail => Some(
+                self.accounts
+                    .get_or_insert_with(tx.client, || Account::new(tx.client)),
+            ),
+        };
+
+        let Some(account) = account else {
             return Err(Error::AccountNotFound { tx });
         };
 
@@ -99,88 +1309,145 @@ impl Ledger {
         let amount = tx.amount()?;
 
         if amount <= Decimal::ZERO {
-            return Err(Error::NegativeAmount { tx });
+            return Err(Error::InvalidAmount { tx });
         }
 
-        if account.available >= amount {
-            account.available -= amount;
-            account.total -= amount;
+        if let Some(limit) = self.config.max_amount.filter(|limit| amount > *limit) {
+            return Err(Error::AmountExceedsLimit { tx, limit });
+        }
 
-            self.tx_log.insert(tx);
+        let Some(fee) = self.config.withdrawal_fee.fee(amount, self.config.rounding) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+        let debit = amount.checked_add(fee);
+        let (Some(available), Some(total), Some(fees_collected)) = (
+            debit.and_then(|debit| account.available.checked_sub(debit)),
+            debit.and_then(|debit| account.total.checked_sub(debit)),
+            self.fees_collected.checked_add(fee),
+        ) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+        let overdraft = self.config.overdraft_limit;
 
-            return Ok(());
+        // Only available funds are drawn, held ones stay covered: as
+        // `total - held == available`, this keeps `total >= held - overdraft`.
+        if available < -overdraft {
+            return Err(Error::InsufficientFunds { tx });
+        }
+
+        if available < self.config.min_balance - overdraft {
+            return Err(Error::BelowMinimumBalance { tx });
+        }
+
+        account.available = available;
+        account.total = total;
+        self.fees_collected = fees_collected;
+
+        self.emit(LedgerEvent::Withdrawn {
+            client: tx.client,
+            tx: tx.id,
+            amount,
+        });
+
+        if !fee.is_zero() {
+            self.emit(LedgerEvent::FeeCharged {
+                client: tx.client,
+                tx: tx.id,
+                amount: fee,
+            });
         }
+        self.ids.insert((tx.client, tx.id));
+        self.withdrawals.insert((tx.client, tx.id), fee);
+        self.tx_log.push(tx);
 
-        Err(Error::InsufficientFunds { tx })
+        Ok(())
     }
 
-    #[inline(always)]
-    fn handle_dispute(&mut self, tx: Transaction) -> Result<()> {
-        if self.tx_log.contains(&tx) {
+    /// Transfers debit the available funds of the source and credit the
+    /// destination, opening its account if needed. Both sides are validated
+    /// before either is touched so a rejected transfer changes nothing.
+    fn handle_transfer(&mut self, tx: Transaction) -> Result<()> {
+        if self.ids.contains(&(tx.client, tx.id)) {
             return Err(Error::DuplicateTransaction { tx });
         }
 
-        let Some(tx_under_dispute) = self
-            .find_tx(|t| {
-                t.id == tx.id
-                    && t.client == tx.client
-                    && matches!(t.r#type, TransactionType::Deposit)
-            })
-            .cloned()
-        else {
-            return Err(Error::TransactionNotFound { tx });
+        let Some(to) = tx.to.filter(|to| *to != tx.client) else {
+            return Err(Error::InvalidTransfer { tx });
         };
 
-        let Some(account) = self.accounts.get_mut(&tx.client) else {
+        let amount = tx.amount()?;
+
+        if amount <= Decimal::ZERO {
+            return Err(Error::InvalidAmount { tx });
+        }
+
+        let Some(source) = self.accounts.get(&tx.client) else {
             return Err(Error::AccountNotFound { tx });
         };
 
-        if account.locked {
+        if source.locked {
             return Err(Error::LockedAccount { tx });
         }
 
-        let amount_disputed = tx_under_dispute.amount()?;
+        let (Some(source_available), Some(source_total)) = (
+            source.available.checked_sub(amount),
+            source.total.checked_sub(amount),
+        ) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+        let overdraft = self.config.overdraft_limit;
 
-        if account.available >= amount_disputed {
-            account.available -= amount_disputed;
-            account.held += amount_disputed;
-        } else {
+        if source_available < -overdraft {
             return Err(Error::InsufficientFunds { tx });
         }
 
-        self.tx_log.insert(tx);
+        if source_available < self.config.min_balance - overdraft {
+            return Err(Error::BelowMinimumBalance { tx });
+        }
+
+        let credited = match self.accounts.get(&to) {
+            Some(dest) if dest.locked && self.config.lock_policy.freezes_deposits() => {
+                return Err(Error::LockedAccount { tx });
+            }
+            Some(dest) => dest
+                .available
+                .checked_add(amount)
+                .zip(dest.total.checked_add(amount)),
+            None => Some((amount, amount)),
+        };
+
+        let Some((available, total)) = credited else {
+            return Err(Error::AmountOverflow { tx });
+        };
+
+        if let Some(source) = self.accounts.get_mut(&tx.client) {
+            source.available = source_available;
+            source.total = source_total;
+        }
+
+        let dest = self.accounts.get_or_insert_with(to, || Account::new(to));
+        dest.available = available;
+        dest.total = total;
+
+        self.emit(LedgerEvent::Transferred {
+            from: tx.client,
+            to,
+            tx: tx.id,
+            amount,
+        });
+        self.ids.insert((tx.client, tx.id));
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
-    #[inline(always)]
-    fn handle_resolve(&mut self, tx: Transaction) -> Result<()> {
-        if self.tx_log.contains(&tx) {
+    /// Adjustments credit or debit an existing account by their signed
+    /// amount, a debit cannot leave `available` negative.
+    fn handle_adjustment(&mut self, tx: Transaction) -> Result<()> {
+        if self.ids.contains(&(tx.client, tx.id)) {
             return Err(Error::DuplicateTransaction { tx });
         }
 
-        if self
-            .find_tx(|t| {
-                t.id == tx.id
-                    && t.client == tx.client
-                    && matches!(t.r#type, TransactionType::Dispute)
-            })
-            .is_none()
-        {
-            return Err(Error::DisputeTxNotFound { tx });
-        }
-
-        let Some(tx_under_dispute) = self
-            .find_tx(|t| {
-                t.id == tx.id
-                    && t.client == tx.client
-                    && matches!(t.r#type, TransactionType::Deposit)
-            })
-            .cloned()
-        else {
-            return Err(Error::TransactionNotFound { tx });
-        };
-
         let Some(account) = self.accounts.get_mut(&tx.client) else {
             return Err(Error::AccountNotFound { tx });
         };
@@ -189,48 +1456,70 @@ impl Ledger {
             return Err(Error::LockedAccount { tx });
         }
 
-        let amount_resolved = tx_under_dispute.amount()?;
+        let amount = tx.amount()?;
 
-        if account.held >= amount_resolved {
-            account.held -= amount_resolved;
-            account.available += amount_resolved;
-        } else {
-            return Err(Error::IncosistentHeldFunds { tx });
+        let (Some(available), Some(total)) = (
+            account.available.checked_add(amount),
+            account.total.checked_add(amount),
+        ) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+
+        if available < Decimal::ZERO {
+            return Err(Error::InsufficientFunds { tx });
         }
 
-        self.tx_log.insert(tx);
+        account.available = available;
+        account.total = total;
+
+        self.emit(LedgerEvent::Adjusted {
+            client: tx.client,
+            tx: tx.id,
+            amount,
+        });
+        self.ids.insert((tx.client, tx.id));
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
+    /// Disputes are checked against the dispute state of the deposit rather
+    /// than the log, a deposit can be disputed again once resolved but a
+    /// charged back one is closed for good and refused with
+    /// `TransactionClosed`.
+    ///
+    /// Only deposits can be disputed. The funds of a withdrawal already left
+    /// the account, holding them again would take them out of `available`
+    /// twice, so disputes on withdrawals are rejected with
+    /// `WithdrawalNotDisputable`.
+    ///
+    /// A dispute carrying an amount holds only that portion of the deposit,
+    /// the resolve or chargeback that follows settles the same portion.
     #[inline(always)]
-    fn handle_chargeback(&mut self, tx: Transaction) -> Result<()> {
-        if self.tx_log.contains(&tx) {
-            return Err(Error::DuplicateTransaction { tx });
+    fn handle_dispute(&mut self, tx: Transaction) -> Result<()> {
+        match self.disputes.get(&(tx.client, tx.id)) {
+            Some(DisputeState::Disputed) => return Err(Error::AlreadyDisputed { tx }),
+            Some(DisputeState::ChargedBack) => return Err(Error::TransactionClosed { tx }),
+            Some(DisputeState::Resolved) | None => {}
         }
 
-        if self
-            .find_tx(|t| {
-                t.id == tx.id
-                    && t.client == tx.client
-                    && matches!(t.r#type, TransactionType::Dispute)
-            })
-            .is_none()
-        {
-            return Err(Error::DisputeTxNotFound { tx });
-        };
+        let Some(deposit) = self.deposits.get(&(tx.client, tx.id)).copied() else {
+            if self.withdrawals.contains_key(&(tx.client, tx.id)) {
+                return Err(Error::WithdrawalNotDisputable { tx });
+            }
 
-        let Some(tx_under_dispute) = self
-            .find_tx(|t| {
-                t.id == tx.id
-                    && t.client == tx.client
-                    && matches!(t.r#type, TransactionType::Deposit)
-            })
-            .cloned()
-        else {
             return Err(Error::TransactionNotFound { tx });
         };
 
+        let amount_disputed = match tx.amount {
+            Some(amount) if amount <= Decimal::ZERO => return Err(Error::InvalidAmount { tx }),
+            Some(amount) if amount > deposit => {
+                return Err(Error::DisputeExceedsDeposit { tx });
+            }
+            Some(amount) => amount,
+            None => deposit,
+        };
+
         let Some(account) = self.accounts.get_mut(&tx.client) else {
             return Err(Error::AccountNotFound { tx });
         };
@@ -239,347 +1528,389 @@ impl Ledger {
             return Err(Error::LockedAccount { tx });
         }
 
-        let amount_chargeback = tx_under_dispute.amount()?;
+        let open_disputes = self.open_disputes.get(&tx.client).copied().unwrap_or(0);
 
-        if account.held >= amount_chargeback {
-            account.held -= amount_chargeback;
-            account.total -= amount_chargeback;
-            account.locked = true;
-        } else {
-            return Err(Error::IncosistentHeldFunds { tx });
+        if let Some(max) = self.config.max_open_disputes_per_account
+            && open_disputes >= max
+        {
+            return Err(Error::TooManyOpenDisputes { tx });
         }
 
-        self.tx_log.insert(tx);
-
-        Ok(())
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use rust_decimal_macros::dec;
+        if account.available < amount_disputed {
+            return Err(Error::InsufficientFunds { tx });
+        }
 
-    use super::*;
+        let Some(held) = account.held.checked_add(amount_disputed) else {
+            return Err(Error::AmountOverflow { tx });
+        };
 
-    pub fn get_account(ledger: &Ledger, client_id: ClientId) -> Option<Account> {
-        ledger.accounts.get(&client_id).map(|acct| {
-            let mut acct = acct.clone();
-            acct.id = client_id;
-            acct
-        })
-    }
+        account.available -= amount_disputed;
+        account.held = held;
 
-    #[test]
-    fn ledger_is_empty_by_default() {
-        let ledger = Ledger::new();
-        assert!(ledger.accounts.is_empty());
-    }
+        debug_assert!(
+            account.held <= account.total,
+            "held exceeds total: {account:?}"
+        );
 
-    #[test]
-    fn process_tx_deposit() -> Result<()> {
-        let mut ledger = Ledger::new();
+        self.disputes
+            .insert((tx.client, tx.id), DisputeState::Disputed);
+        self.open_disputes.insert(tx.client, open_disputes + 1);
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(100.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
+        if amount_disputed < deposit {
+            self.disputed_amounts
+                .insert((tx.client, tx.id), amount_disputed);
+        } else {
+            self.disputed_amounts.remove(&(tx.client, tx.id));
+        }
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+        if self.config.dispute_expiry.is_some() {
+            let opened = DisputeOpened {
+                seq: self.applied + 1,
+                at: tx.timestamp.or(self.latest),
+            };
+            self.dispute_opened.insert((tx.client, tx.id), opened);
+        }
 
-        assert_eq!(account.available, dec!(100.0));
-        assert_eq!(ledger.tx_log.len(), 1);
+        self.emit(LedgerEvent::FundsHeld {
+            client: tx.client,
+            tx: tx.id,
+            amount: amount_disputed,
+        });
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
-    #[test]
-    fn process_tx_deposit_withdrawal() -> Result<()> {
-        let mut ledger = Ledger::new();
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(100.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(100.0)),
-            r#type: TransactionType::Withdrawal,
-            client: 1,
-            id: 2,
-        })?;
-
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+    /// Resolving releases the held funds of a deposit currently under
+    /// dispute, any other deposit is refused with `DisputeTxNotFound`.
+    #[inline(always)]
+    fn handle_resolve(&mut self, tx: Transaction) -> Result<()> {
+        if !self.is_disputed(&tx) {
+            return Err(Error::DisputeTxNotFound { tx });
+        }
 
-        assert_eq!(account.available, dec!(0.0));
-        assert_eq!(ledger.tx_log.len(), 2);
+        let Some(amount_resolved) = self.disputed_amount(&tx) else {
+            return Err(Error::TransactionNotFound { tx });
+        };
+        let amount_resolved = amount_resolved + self.held_interest(&tx);
 
-        Ok(())
-    }
+        let Some(account) = self.accounts.get_mut(&tx.client) else {
+            return Err(Error::AccountNotFound { tx });
+        };
 
-    #[test]
-    fn process_tx_withdrawal_handles_insufficient_funds() -> Result<()> {
-        let mut ledger = Ledger::new();
+        if account.locked {
+            return Err(Error::LockedAccount { tx });
+        }
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(2.0)),
-            r#type: TransactionType::Deposit,
-            client: 2,
-            id: 1,
-        })?;
+        if account.held < amount_resolved {
+            return Err(Error::IncosistentHeldFunds { tx });
+        }
 
-        let tx = Transaction {
-            amount: Some(dec!(3.0)),
-            r#type: TransactionType::Withdrawal,
-            client: 2,
-            id: 2,
+        let Some(available) = account.available.checked_add(amount_resolved) else {
+            return Err(Error::AmountOverflow { tx });
         };
-        let result = ledger.process_tx(tx.clone());
 
-        assert!(result.is_err());
-        assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+        account.held -= amount_resolved;
+        account.available = available;
 
-        let account = get_account(&ledger, 2).expect("expected account for client.");
+        debug_assert!(
+            account.held <= account.total,
+            "held exceeds total: {account:?}"
+        );
 
-        assert_eq!(account.available, dec!(2.0));
-        assert_eq!(ledger.tx_log.len(), 1);
+        self.disputes
+            .insert((tx.client, tx.id), DisputeState::Resolved);
+        self.disputed_amounts.remove(&(tx.client, tx.id));
+        self.held_interest.remove(&(tx.client, tx.id));
+        self.close_dispute(tx.client);
+        self.emit(LedgerEvent::FundsReleased {
+            client: tx.client,
+            tx: tx.id,
+            amount: amount_resolved,
+        });
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
-    #[test]
-    fn process_tx_two_accounts() -> Result<()> {
-        let mut ledger = Ledger::new();
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(1.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(2.0)),
-            r#type: TransactionType::Deposit,
-            client: 2,
-            id: 2,
-        })?;
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(2.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 3,
-        })?;
-
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(1.5)),
-            r#type: TransactionType::Withdrawal,
-            client: 1,
-            id: 4,
-        })?;
-
-        let result = ledger.process_tx(Transaction {
-            amount: Some(dec!(3.0)),
-            r#type: TransactionType::Withdrawal,
-            client: 2,
-            id: 5,
-        });
+    /// Charging back a deposit currently under dispute removes its held funds
+    /// from the account and locks it. Only deposits can be disputed so there
+    /// is no withdrawal direction to account for.
+    #[inline(always)]
+    fn handle_chargeback(&mut self, tx: Transaction) -> Result<()> {
+        if !self.is_disputed(&tx) {
+            return Err(Error::DisputeTxNotFound { tx });
+        }
 
-        assert!(result.is_err(), "should fail due to insufficient funds");
+        let Some(amount_chargeback) = self.disputed_amount(&tx) else {
+            return Err(Error::TransactionNotFound { tx });
+        };
+        let interest = self.held_interest(&tx);
+        let amount_chargeback = amount_chargeback + interest;
 
-        let account_1 = get_account(&ledger, 1).expect("expected account for client.");
-        let account_2 = get_account(&ledger, 2).expect("expected account for client.");
+        let Some(account) = self.accounts.get_mut(&tx.client) else {
+            return Err(Error::AccountNotFound { tx });
+        };
 
-        assert_eq!(
-            account_1,
-            Account {
-                id: 1,
-                available: dec!(1.5),
-                held: dec!(0.0),
-                locked: false,
-                total: dec!(1.5),
-            }
-        );
+        if account.locked {
+            return Err(Error::LockedAccount { tx });
+        }
 
-        assert_eq!(
-            account_2,
-            Account {
-                id: 2,
-                available: dec!(2.0),
-                held: dec!(0.0),
-                locked: false,
-                total: dec!(2.0),
-            }
+        if account.held < amount_chargeback {
+            return Err(Error::IncosistentHeldFunds { tx });
+        }
+
+        let Some(total) = account.total.checked_sub(amount_chargeback) else {
+            return Err(Error::AmountOverflow { tx });
+        };
+
+        account.held -= amount_chargeback;
+        account.total = total;
+        account.locked = true;
+
+        debug_assert!(
+            account.held <= account.total,
+            "held exceeds total: {account:?}"
         );
 
-        assert_eq!(ledger.tx_log.len(), 4);
+        self.disputes
+            .insert((tx.client, tx.id), DisputeState::ChargedBack);
+
+        if !interest.is_zero() {
+            self.held_interest.remove(&(tx.client, tx.id));
+            *self.interest_accrued.entry(tx.client).or_default() -= interest;
+        }
+
+        self.close_dispute(tx.client);
+        self.emit(LedgerEvent::ChargedBack {
+            client: tx.client,
+            tx: tx.id,
+            amount: amount_chargeback,
+        });
+        self.emit(LedgerEvent::AccountLocked { client: tx.client });
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
-    #[test]
-    fn process_tx_dispute() -> Result<()> {
-        let mut ledger = Ledger::new();
+    /// Brings the accounts `tx` touches and the logged transaction it refers
+    /// to into memory when only the store has them, e.g. after a restart.
+    fn load_from_store(&mut self, tx: &Transaction) -> Result<()> {
+        for client in std::iter::once(tx.client).chain(tx.to) {
+            if self.accounts.get(&client).is_none()
+                && let Some(acct) = self.store.get_account(client)?
+            {
+                self.accounts.insert(client, acct);
+            }
+        }
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(10.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
+        let key = (tx.client, tx.id);
 
-        ledger.process_tx(Transaction {
-            amount: None,
-            r#type: TransactionType::Dispute,
-            client: 1,
-            id: 1,
-        })?;
+        if self.ids.contains(&key) {
+            return Ok(());
+        }
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+        if let Some((logged, fee)) = self.store.find_tx(tx.client, tx.id)? {
+            match logged.r#type {
+                TransactionType::Deposit => {
+                    if let Some(amount) = logged.amount {
+                        self.deposits.insert(key, amount);
+                    }
+                }
+                TransactionType::Withdrawal => {
+                    self.withdrawals.insert(key, fee);
+                }
+                _ => {}
+            }
 
-        assert!(!account.locked);
-        assert_eq!(account.available, dec!(0.0));
-        assert_eq!(account.held, dec!(10.0));
-        assert_eq!(account.total, dec!(10.0));
-        assert_eq!(ledger.tx_log.len(), 2);
+            self.ids.insert(key);
+            self.tx_log.push(logged);
+        }
 
         Ok(())
     }
 
-    #[test]
-    fn process_tx_dispute_tx_not_found() -> Result<()> {
-        let mut ledger = Ledger::new();
+    /// Writes the accounts `tx` touched through to the store, and `tx`
+    /// itself once `applied`, all of it or nothing.
+    fn persist(&mut self, tx: &Transaction, applied: bool) -> Result<()> {
+        self.store.begin()?;
+
+        let written = std::iter::once(tx.client)
+            .chain(tx.to)
+            .try_for_each(|client| self.persist_account(client))
+            .and_then(|()| {
+                if applied {
+                    let fee = match tx.r#type {
+                        TransactionType::Withdrawal => self.withdrawals.get(&(tx.client, tx.id)),
+                        _ => None,
+                    };
+
+                    self.store.insert_tx(tx, fee.copied().unwrap_or_default())
+                } else {
+                    Ok(())
+                }
+            });
+
+        match written {
+            Ok(()) => self.store.commit(),
+            Err(e) => {
+                if let Err(rollback) = self.store.rollback() {
+                    error!("Error rolling back {:?}: {:?}", tx, rollback);
+                }
+
+                Err(e)
+            }
+        }
+    }
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(10.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
+    fn persist_account(&mut self, client: ClientId) -> Result<()> {
+        match self.accounts.get(&client) {
+            Some(acct) => self.store.upsert_account(client, acct),
+            None => Ok(()),
+        }
+    }
 
-        let tx = Transaction {
-            amount: None,
-            r#type: TransactionType::Dispute,
-            client: 1,
-            id: 3,
-        };
-        let result = ledger.process_tx(tx.clone());
+    /// Reversing a chargeback credits the charged back funds again, the
+    /// deposit is left as if its dispute had been resolved. The account is
+    /// unlocked once none of its deposits remain charged back. Anything but
+    /// a charged back deposit is refused with `NotChargedBack`.
+    #[inline(always)]
+    fn handle_chargeback_reversal(&mut self, tx: Transaction) -> Result<()> {
+        if self.disputes.get(&(tx.client, tx.id)) != Some(&DisputeState::ChargedBack) {
+            return Err(Error::NotChargedBack { tx });
+        }
 
-        assert!(result.is_err());
-        assert!(matches!(result, Err(Error::TransactionNotFound { tx: _ })));
+        let Some(amount_reversed) = self.disputed_amount(&tx) else {
+            return Err(Error::TransactionNotFound { tx });
+        };
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+        let Some(account) = self.accounts.get_mut(&tx.client) else {
+            return Err(Error::AccountNotFound { tx });
+        };
 
-        assert!(!account.locked);
-        assert_eq!(account.held, dec!(0.0));
-        assert_eq!(account.available, dec!(10.0));
-        assert_eq!(account.total, dec!(10.0));
-        assert_eq!(ledger.tx_log.len(), 1);
+        let (Some(available), Some(total)) = (
+            account.available.checked_add(amount_reversed),
+            account.total.checked_add(amount_reversed),
+        ) else {
+            return Err(Error::AmountOverflow { tx });
+        };
 
-        Ok(())
-    }
+        account.available = available;
+        account.total = total;
 
-    #[test]
-    fn process_tx_dispute_resolve() -> Result<()> {
-        let mut ledger = Ledger::new();
+        self.disputes
+            .insert((tx.client, tx.id), DisputeState::Resolved);
+        self.disputed_amounts.remove(&(tx.client, tx.id));
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(10.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
+        let unlocked = !self.has_chargebacks(tx.client);
 
-        ledger.process_tx(Transaction {
-            amount: None,
-            r#type: TransactionType::Dispute,
-            client: 1,
-            id: 1,
-        })?;
+        if unlocked && let Some(account) = self.accounts.get_mut(&tx.client) {
+            account.locked = false;
+        }
 
-        ledger.process_tx(Transaction {
-            amount: None,
-            r#type: TransactionType::Resolve,
-            client: 1,
-            id: 1,
-        })?;
+        self.emit(LedgerEvent::ChargebackReversed {
+            client: tx.client,
+            tx: tx.id,
+            amount: amount_reversed,
+        });
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+        if unlocked {
+            self.emit(LedgerEvent::AccountUnlocked { client: tx.client });
+        }
 
-        assert!(!account.locked);
-        assert_eq!(account.available, dec!(10.0));
-        assert_eq!(account.held, dec!(0.0));
-        assert_eq!(account.total, dec!(10.0));
-        assert_eq!(ledger.tx_log.len(), 3);
+        self.tx_log.push(tx);
 
         Ok(())
     }
 
-    #[test]
-    fn process_tx_dispute_deposit_chargeback() -> Result<()> {
-        let mut ledger = Ledger::new();
+    /// Whether any deposit of `client` is currently charged back.
+    fn has_chargebacks(&self, client: ClientId) -> bool {
+        self.disputes
+            .iter()
+            .any(|((id, _), state)| *id == client && *state == DisputeState::ChargedBack)
+    }
 
-        ledger.process_tx(Transaction {
-            amount: Some(dec!(100.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        })?;
+    /// Sum of the balances of `acct` and `merged`, if any, locked if either
+    /// is. `None` on overflow.
+    fn add_balances(merged: Option<&Account>, acct: &Account) -> Option<Account> {
+        let Some(merged) = merged else {
+            return Some(acct.clone());
+        };
 
-        ledger.process_tx(Transaction {
-            amount: None,
-            r#type: TransactionType::Dispute,
-            client: 1,
-            id: 1,
-        })?;
+        Some(Account {
+            id: merged.id,
+            available: merged.available.checked_add(acct.available)?,
+            held: merged.held.checked_add(acct.held)?,
+            total: merged.total.checked_add(acct.total)?,
+            locked: merged.locked || acct.locked,
+        })
+    }
 
-        ledger.process_tx(Transaction {
-            amount: None,
-            r#type: TransactionType::Chargeback,
-            client: 1,
-            id: 1,
-        })?;
+    fn is_disputed(&self, tx: &Transaction) -> bool {
+        self.disputes.get(&(tx.client, tx.id)) == Some(&DisputeState::Disputed)
+    }
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+    /// Funds held by the dispute on the deposit referenced by `tx`, the
+    /// disputed portion for partial disputes or else the whole deposit.
+    fn disputed_amount(&self, tx: &Transaction) -> Option<Decimal> {
+        let key = (tx.client, tx.id);
 
-        assert!(account.locked);
-        assert_eq!(account.available, dec!(0.0));
-        assert_eq!(account.held, dec!(0.0));
-        assert_eq!(account.total, dec!(0.0));
-        assert_eq!(ledger.tx_log.len(), 3);
+        self.disputed_amounts
+            .get(&key)
+            .or_else(|| self.deposits.get(&key))
+            .copied()
+    }
 
-        Ok(())
+    /// Interest accrued so far on the dispute of the deposit referenced by
+    /// `tx`.
+    fn held_interest(&self, tx: &Transaction) -> Decimal {
+        self.held_interest
+            .get(&(tx.client, tx.id))
+            .copied()
+            .unwrap_or_default()
     }
 
-    #[test]
-    fn check_duplicate_transaction() -> Result<()> {
-        let mut ledger = Ledger::new();
+    fn close_dispute(&mut self, client: ClientId) {
+        if let Some(open) = self.open_disputes.get_mut(&client) {
+            *open = open.saturating_sub(1);
+        }
+    }
+}
 
-        let tx = Transaction {
-            amount: Some(dec!(50.0)),
-            r#type: TransactionType::Deposit,
-            client: 1,
-            id: 1,
-        };
+/// Whether `amount` is at most `limit`, anything is without a limit.
+#[inline(always)]
+fn within(limit: Option<Decimal>, amount: Decimal) -> bool {
+    limit.is_none_or(|limit| amount <= limit)
+}
 
-        ledger.process_tx(tx.clone())?;
-        let result = ledger.process_tx(tx.clone());
+/// Runs the ledger tests in `ledger/suite.rs` against the memory store.
+#[cfg(test)]
+#[path = "ledger"]
+mod tests {
+    use crate::store::MemoryStore;
 
-        assert!(result.is_err());
-        assert!(matches!(result, Err(Error::DuplicateTransaction { tx: _ })));
+    type TestStore = MemoryStore;
 
-        let account = get_account(&ledger, 1).expect("expected account for client.");
+    fn test_store() -> TestStore {
+        MemoryStore
+    }
 
-        assert!(!account.locked);
-        assert_eq!(account.available, dec!(50.0));
-        assert_eq!(account.held, dec!(0.0));
-        assert_eq!(account.total, dec!(50.0));
-        assert_eq!(ledger.tx_log.len(), 1);
+    mod suite;
+}
 
-        Ok(())
+/// Runs the same tests against an in-memory SQLite database.
+#[cfg(all(test, feature = "sqlite"))]
+#[path = "ledger"]
+mod sqlite_tests {
+    use crate::store::SqliteStore;
+
+    type TestStore = SqliteStore;
+
+    fn test_store() -> TestStore {
+        SqliteStore::open_in_memory().expect("in-memory database")
     }
+
+    // The suite is loaded once per store on purpose
+    #[allow(clippy::duplicate_mod)]
+    mod suite;
 }
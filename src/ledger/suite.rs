@@ -0,0 +1,3119 @@
+//! Ledger tests, run once per store by the modules declaring `suite`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, NaiveDate};
+use rust_decimal_macros::dec;
+
+use super::{TestStore, test_store};
+use crate::config::{DisputeExpiry, FeePolicy, LockPolicy, RoundingMode};
+use crate::csv_io::CsvWriter;
+use crate::ledger::*;
+use crate::tx::sort_by_effective_date;
+
+/// Ledger under test, backed by the store of the enclosing module.
+type Ledger = crate::ledger::Ledger<TestStore>;
+
+/// Empty ledger with the default config.
+fn new_ledger() -> Ledger {
+    ledger_with_config(LedgerConfig::default())
+}
+
+fn ledger_with_config(config: LedgerConfig) -> Ledger {
+    Ledger::with_store(config, test_store())
+}
+
+fn restore_ledger(snapshot: LedgerSnapshot) -> Ledger {
+    restore_ledger_with_config(snapshot, LedgerConfig::default())
+}
+
+fn restore_ledger_with_config(snapshot: LedgerSnapshot, config: LedgerConfig) -> Ledger {
+    Ledger::restore_with_store(snapshot, config, test_store())
+}
+
+pub fn get_account(ledger: &Ledger, client_id: ClientId) -> Option<Account> {
+    ledger.accounts.get(&client_id).map(|acct| {
+        let mut acct = acct.clone();
+        acct.id = Some(client_id);
+        acct
+    })
+}
+
+#[test]
+fn ledger_is_empty_by_default() {
+    let ledger = new_ledger();
+    assert!(ledger.accounts.is_empty());
+}
+
+#[test]
+fn seed_opening_balances() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.seed_opening(vec![Account {
+        id: Some(3),
+        available: dec!(5.0),
+        held: dec!(1.0),
+        total: dec!(6.0),
+        locked: false,
+    }])?;
+
+    ledger.process_tx(Transaction {
+        client: 3,
+        ..tx(TransactionType::Withdrawal, 1, Some(dec!(4.0)))
+    })?;
+
+    let account = get_account(&ledger, 3).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(1.0));
+    assert_eq!(account.total, dec!(2.0));
+
+    Ok(())
+}
+
+#[test]
+fn seed_opening_rejects_inconsistent_total() {
+    let mut ledger = new_ledger();
+
+    let result = ledger.seed_opening(vec![
+        Account {
+            id: Some(1),
+            available: dec!(5.0),
+            held: dec!(0.0),
+            total: dec!(5.0),
+            locked: false,
+        },
+        Account {
+            id: Some(2),
+            available: dec!(5.0),
+            held: dec!(1.0),
+            total: dec!(5.0),
+            locked: false,
+        },
+    ]);
+
+    assert!(matches!(
+        result,
+        Err(Error::InconsistentOpeningBalances { clients }) if clients == vec![2]
+    ));
+    assert!(ledger.accounts.is_empty());
+}
+
+#[test]
+fn process_tx_for_client_zero() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(Transaction {
+        client: 0,
+        ..tx(TransactionType::Deposit, 1, Some(dec!(3.0)))
+    })?;
+
+    let summary = ledger.accounts_summary();
+
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].id, Some(0));
+    assert_eq!(summary[0].total, dec!(3.0));
+    assert_eq!(ledger.accounts.get(&0).and_then(|acct| acct.id), Some(0));
+    assert_eq!(Account::default().id, None);
+
+    Ok(())
+}
+
+/// Accounts summary as written by the CSV output.
+fn summary_csv(ledger: &Ledger) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    let mut writer = CsvWriter::from_writer(&mut buf);
+
+    for acct in ledger.accounts_summary() {
+        writer.write(&acct)?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+
+    Ok(String::from_utf8(buf)?)
+}
+
+#[test]
+fn summary_rows_report_account_client() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(deposit_for(42, 1, dec!(1.0)))?;
+
+    assert_eq!(
+        summary_csv(&ledger)?,
+        "client,available,held,total,locked\n42,1.0000,0.0000,1.0000,false\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn accounts_summary_sets_client_of_every_row() -> anyhow::Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        dense_accounts: 8,
+        hot_accounts: 1,
+        ..LedgerConfig::default()
+    });
+
+    // Client 7 lands in the dense vector, 1 and 255 in the hot set and
+    // the map
+    for (id, client) in [(1, 255), (2, 7), (3, 1)] {
+        ledger.process_tx(deposit_for(client, id, dec!(1.0)))?;
+    }
+
+    let summary = summary_csv(&ledger)?;
+    let clients: Vec<&str> = summary
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split(',').next())
+        .collect();
+
+    assert_eq!(clients, vec!["1", "7", "255"]);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_deposit() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(100.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_deposit_withdrawal() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(100.0))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(ledger.tx_log.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_handles_insufficient_funds() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Deposit, 1, Some(dec!(2.0)))
+    })?;
+
+    let tx = Transaction {
+        client: 2,
+        ..tx(TransactionType::Withdrawal, 2, Some(dec!(3.0)))
+    };
+    let result = ledger.process_tx(tx.clone());
+
+    assert!(result.is_err());
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 2).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(2.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn withdrawal_fees_are_debited_and_collected() -> Result<()> {
+    for (policy, fee) in [
+        (FeePolicy::Flat(dec!(0.5)), dec!(0.5)),
+        (FeePolicy::Percentage(dec!(1.5)), dec!(0.06)),
+    ] {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            withdrawal_fee: policy,
+            ..LedgerConfig::default()
+        });
+
+        ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+        ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(4.0))))?;
+
+        let account = get_account(&ledger, 1).expect("expected account for client.");
+
+        assert_eq!(account.available, dec!(6.0) - fee);
+        assert_eq!(account.total, dec!(6.0) - fee);
+        assert_eq!(ledger.fees_collected(), fee);
+        assert_eq!(ledger.verify_replay(), Ok(()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn percentage_fee_overflow_is_rejected() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        withdrawal_fee: FeePolicy::Percentage(dec!(500)),
+        ..LedgerConfig::default()
+    });
+    let huge = dec!(1_000_000_000_000_000_000_000_000_000);
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(huge)))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(huge)));
+
+    assert!(matches!(result, Err(Error::AmountOverflow { tx: _ })));
+    assert_eq!(ledger.fees_collected(), dec!(0));
+
+    Ok(())
+}
+
+#[test]
+fn replay_keeps_fees_charged_under_another_policy() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        withdrawal_fee: FeePolicy::Flat(dec!(1.0)),
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(4.0))))?;
+
+    // Resumed without a fee, the earlier withdrawal keeps its fee
+    let mut resumed = restore_ledger_with_config(ledger.snapshot(), LedgerConfig::default());
+    resumed.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(1.0))))?;
+
+    let account = get_account(&resumed, 1).expect("expected account for client.");
+
+    assert_eq!(account.total, dec!(4.0));
+    assert_eq!(resumed.fees_collected(), dec!(1.0));
+    assert_eq!(resumed.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn percentage_fees_follow_rounding_mode() -> Result<()> {
+    // 0.5% of 0.01 is 0.00005, exactly between two fourth places
+    for (rounding, fee) in [
+        (RoundingMode::HalfEven, dec!(0.0000)),
+        (RoundingMode::HalfUp, dec!(0.0001)),
+        (RoundingMode::TowardZero, dec!(0.0000)),
+    ] {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            withdrawal_fee: FeePolicy::Percentage(dec!(0.5)),
+            rounding,
+            ..LedgerConfig::default()
+        });
+
+        ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(1.0))))?;
+        ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(0.01))))?;
+
+        assert_eq!(ledger.fees_collected(), fee, "{rounding:?}");
+    }
+
+    Ok(())
+}
+
+/// Ledger accruing 1% per period whose client 1 disputes a deposit of
+/// 100.
+fn interest_ledger() -> Result<Ledger> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        held_interest_rate: dec!(0.01),
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(50.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    Ok(ledger)
+}
+
+#[test]
+fn resolve_releases_principal_and_accrued_interest() -> Result<()> {
+    let mut ledger = interest_ledger()?;
+    ledger.accrue_interest(1)?;
+    ledger.accrue_interest(2)?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(50.0));
+    assert_eq!(account.held, dec!(103.0));
+    assert_eq!(account.total, dec!(153.0));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    ledger.process_tx(tx(TransactionType::Resolve, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(153.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(153.0));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    // Resolved disputes stop accruing
+    ledger.accrue_interest(1)?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(dec!(153.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn chargeback_forfeits_accrued_interest() -> Result<()> {
+    let mut ledger = interest_ledger()?;
+    ledger.accrue_interest(2)?;
+
+    // Held interest survives a snapshot
+    let mut ledger = restore_ledger(ledger.snapshot());
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(50.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(50.0));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn negative_interest_rate_is_refused() -> Result<()> {
+    let mut ledger = interest_ledger()?;
+    ledger.config.held_interest_rate = dec!(-0.01);
+
+    let result = ledger.accrue_interest(1);
+
+    assert!(matches!(
+        result,
+        Err(Error::InvalidInterestRate { rate }) if rate == dec!(-0.01)
+    ));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.held),
+        Some(dec!(100.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn interest_rate_overflow_is_rejected() -> Result<()> {
+    let mut ledger = interest_ledger()?;
+    ledger.config.held_interest_rate = Decimal::MAX;
+
+    let result = ledger.accrue_interest(u32::MAX);
+
+    assert!(matches!(result, Err(Error::AmountOverflow { tx: _ })));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.held),
+        Some(dec!(100.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn withdrawal_fee_must_be_covered() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        withdrawal_fee: FeePolicy::Flat(dec!(1.0)),
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    // The amount alone fits, amount plus fee does not
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(9.5))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+    assert_eq!(ledger.fees_collected(), dec!(0));
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(9.0))))?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.available),
+        Some(dec!(0.0))
+    );
+    assert_eq!(ledger.fees_collected(), dec!(1.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_cannot_draw_held_funds() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(50.0))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(100.0));
+    assert_eq!(account.total, dec!(100.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_draws_available_left_by_partial_dispute() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, Some(dec!(60.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(40.0))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(0.01))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(60.0));
+    assert_eq!(account.total, dec!(60.0));
+    assert!(account.total >= account.held);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_into_overdraft() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        overdraft_limit: dec!(10.0),
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(8.0))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(-3.0));
+    assert_eq!(account.total, dec!(-3.0));
+
+    // Down to exactly the limit
+    ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(7.0))))?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.available),
+        Some(dec!(-10.0))
+    );
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 4, Some(dec!(0.0001))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(dec!(-10.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_respects_min_balance() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        min_balance: dec!(10.0),
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(50.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(40.0))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(0.01))));
+
+    assert!(matches!(result, Err(Error::BelowMinimumBalance { tx: _ })));
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 4, Some(dec!(20.0))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_refuses_accounts_holding_more_than_total() -> Result<()> {
+    // Consistent with `total == available + held`, yet holding more
+    // than the total, only opening balances can produce it
+    let opening = Account {
+        id: Some(1),
+        available: dec!(-3.0),
+        held: dec!(10.0),
+        total: dec!(7.0),
+        locked: false,
+    };
+    let deposit = tx(TransactionType::Deposit, 1, Some(dec!(1.0)));
+
+    let mut ledger = ledger_with_config(LedgerConfig {
+        check_held: true,
+        ..LedgerConfig::default()
+    });
+    ledger.seed_opening(vec![opening.clone()])?;
+
+    let result = ledger.process_tx(deposit.clone());
+
+    assert!(matches!(result, Err(Error::IncosistentHeldFunds { tx: _ })));
+    assert_eq!(get_account(&ledger, 1), Some(opening.clone()));
+
+    let mut ledger = new_ledger();
+    ledger.seed_opening(vec![opening])?;
+    ledger.process_tx(deposit)?;
+
+    Ok(())
+}
+
+fn tx(r#type: TransactionType, id: TransactionId, amount: Option<Decimal>) -> Transaction {
+    Transaction::new(r#type, 1, id, amount)
+}
+
+#[test]
+fn process_tx_dispute_respects_open_disputes_cap() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        max_open_disputes_per_account: Some(2),
+        ..LedgerConfig::default()
+    });
+
+    for id in 1..=3 {
+        ledger.process_tx(tx(TransactionType::Deposit, id, Some(dec!(10.0))))?;
+    }
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 3, None));
+
+    assert!(matches!(result, Err(Error::TooManyOpenDisputes { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.held, dec!(20.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_settled_disputes_free_the_cap() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        max_open_disputes_per_account: Some(1),
+        ..LedgerConfig::default()
+    });
+
+    for id in 1..=3 {
+        ledger.process_tx(tx(TransactionType::Deposit, id, Some(dec!(10.0))))?;
+    }
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Resolve, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 3, None));
+
+    assert!(matches!(result, Err(Error::TooManyOpenDisputes { tx: _ })));
+
+    ledger.process_tx(tx(TransactionType::Chargeback, 2, None))?;
+
+    // The chargeback locks the account, the cap is no longer what stops it
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 3, None));
+
+    assert!(matches!(result, Err(Error::LockedAccount { tx: _ })));
+    assert_eq!(ledger.open_disputes.get(&1), Some(&0));
+
+    Ok(())
+}
+
+#[test]
+fn checkpointed_run_matches_uninterrupted_run() -> anyhow::Result<()> {
+    let txs = vec![
+        tx(TransactionType::Deposit, 1, Some(dec!(1.0))),
+        deposit_for(2, 2, dec!(2.0)),
+        tx(TransactionType::Deposit, 3, Some(dec!(2.0))),
+        tx(TransactionType::Dispute, 3, None),
+        tx(TransactionType::Withdrawal, 4, Some(dec!(0.5))),
+        tx(TransactionType::Chargeback, 3, None),
+        deposit_for(2, 5, dec!(1.25)),
+    ];
+
+    let mut uninterrupted = new_ledger();
+    uninterrupted
+        .apply_from_iter(txs.clone(), true)
+        .map_err(|(_, e)| e)?;
+
+    let mut first = new_ledger();
+    first
+        .apply_from_iter(txs[..4].to_vec(), true)
+        .map_err(|(_, e)| e)?;
+
+    let json = serde_json::to_string(&first.snapshot())?;
+    let mut resumed = restore_ledger(serde_json::from_str(&json)?);
+    resumed
+        .apply_from_iter(txs[4..].to_vec(), true)
+        .map_err(|(_, e)| e)?;
+
+    assert_eq!(summary_csv(&resumed)?, summary_csv(&uninterrupted)?);
+    assert_eq!(resumed.snapshot(), uninterrupted.snapshot());
+
+    Ok(())
+}
+
+#[test]
+fn restored_snapshot_resolves_open_dispute() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let json = serde_json::to_string(&ledger.snapshot())?;
+    let mut restored = restore_ledger(serde_json::from_str(&json)?);
+
+    assert_eq!(restored.snapshot().accounts, ledger.snapshot().accounts);
+
+    restored.process_tx(tx(TransactionType::Resolve, 1, None))?;
+
+    let account = get_account(&restored, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(15.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(restored.open_disputes.get(&1), Some(&0));
+
+    // The restored dispute state no longer holds the resolved deposit
+    restored.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let result = restored.process_tx(tx(TransactionType::Dispute, 1, None));
+
+    assert!(matches!(result, Err(Error::AlreadyDisputed { tx: _ })));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_twice_is_rejected() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let result = ledger.process_tx(Transaction {
+        memo: Some("second attempt".to_string()),
+        ..tx(TransactionType::Dispute, 1, None)
+    });
+
+    assert!(matches!(result, Err(Error::AlreadyDisputed { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.total, dec!(15.0));
+    assert_eq!(ledger.open_disputes.get(&1), Some(&1));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_after_resolve() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Resolve, 1, None))?;
+
+    assert_eq!(ledger.disputes.get(&(1, 1)), Some(&DisputeState::Resolved));
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.disputes.get(&(1, 1)), Some(&DisputeState::Disputed));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_after_chargeback() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 1, None));
+
+    assert!(matches!(result, Err(Error::TransactionClosed { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(account.locked);
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(0.0));
+    assert_eq!(
+        ledger.disputes.get(&(1, 1)),
+        Some(&DisputeState::ChargedBack)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_resolve_without_dispute() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+
+    // Deposit 1 was never disputed, the funds held for 2 must stay held
+    let result = ledger.process_tx(tx(TransactionType::Resolve, 1, None));
+
+    assert!(matches!(result, Err(Error::DisputeTxNotFound { tx: _ })));
+
+    ledger.process_tx(tx(TransactionType::Resolve, 2, None))?;
+
+    let result = ledger.process_tx(Transaction {
+        memo: Some("resolved twice".to_string()),
+        ..tx(TransactionType::Resolve, 2, None)
+    });
+
+    assert!(matches!(result, Err(Error::DisputeTxNotFound { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(15.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_chargeback_without_dispute() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Chargeback, 1, None));
+
+    assert!(matches!(result, Err(Error::DisputeTxNotFound { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.held, dec!(5.0));
+    assert_eq!(account.total, dec!(15.0));
+    assert_eq!(ledger.open_disputes.get(&1), Some(&1));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_rejects_non_positive_amounts() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let rejected = [
+        tx(TransactionType::Deposit, 2, Some(dec!(-50.0))),
+        tx(TransactionType::Withdrawal, 3, Some(dec!(-50.0))),
+        tx(TransactionType::Deposit, 4, Some(dec!(0.0))),
+        tx(TransactionType::Withdrawal, 5, Some(dec!(0.0))),
+    ];
+
+    for t in rejected {
+        let result = ledger.process_tx(t);
+
+        assert!(matches!(result, Err(Error::InvalidAmount { tx: _ })));
+    }
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_detects_broken_invariant() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        check_invariants: true,
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    // Simulate a handler bug leaving funds held without taking them out
+    // of `available`
+    ledger.accounts.get_mut(&1).expect("account 1").held = dec!(3.0);
+
+    let result = ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(1.0))));
+
+    assert!(matches!(result, Err(Error::BrokenInvariant { tx: _ })));
+
+    // The deposit was rejected before touching the account
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    // Without the check the same account is processed silently
+    ledger.config.check_invariants = false;
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(1.0))))?;
+
+    Ok(())
+}
+
+fn deposit_for(client: ClientId, id: TransactionId, amount: Decimal) -> Transaction {
+    Transaction {
+        client,
+        ..tx(TransactionType::Deposit, id, Some(amount))
+    }
+}
+
+#[test]
+fn account_history_keeps_application_order() -> Result<()> {
+    let mut ledger = new_ledger();
+    let txs = [
+        deposit_for(2, 1, dec!(4.0)),
+        deposit_for(1, 2, dec!(5.0)),
+        tx(TransactionType::Dispute, 2, None),
+        tx(TransactionType::Resolve, 2, None),
+        tx(TransactionType::Withdrawal, 3, Some(dec!(1.0))),
+        Transaction {
+            client: 2,
+            ..tx(TransactionType::Withdrawal, 4, Some(dec!(1.0)))
+        },
+        transfer(5, 2, dec!(2.0)),
+    ];
+
+    for tx in txs.iter().cloned() {
+        ledger.process_tx(tx)?;
+    }
+
+    // Rejected, so not part of the history
+    let _ = ledger.process_tx(tx(TransactionType::Withdrawal, 6, Some(dec!(100.0))));
+
+    let ids = |client| -> Vec<TransactionId> {
+        ledger
+            .account_history(client)
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect()
+    };
+
+    assert_eq!(ids(1), vec![2, 2, 2, 3, 5]);
+    assert_eq!(ids(2), vec![1, 4, 5]);
+    assert_eq!(ledger.account_history(1)[3], &txs[4]);
+    assert!(ledger.account_history(3).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn account_history_keeps_repeated_support_rows() -> Result<()> {
+    let mut ledger = new_ledger();
+    let txs = [
+        tx(TransactionType::Deposit, 1, Some(dec!(10.0))),
+        tx(TransactionType::Dispute, 1, None),
+        tx(TransactionType::Resolve, 1, None),
+        tx(TransactionType::Dispute, 1, None),
+        tx(TransactionType::Chargeback, 1, None),
+        tx(TransactionType::ChargebackReversal, 1, None),
+        tx(TransactionType::Dispute, 1, None),
+        tx(TransactionType::Chargeback, 1, None),
+        tx(TransactionType::ChargebackReversal, 1, None),
+    ];
+
+    for tx in txs.iter().cloned() {
+        ledger.process_tx(tx)?;
+    }
+
+    let history: Vec<&Transaction> = ledger.account_history(1);
+
+    assert_eq!(history, txs.iter().collect::<Vec<_>>());
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    let restored = restore_ledger(ledger.snapshot());
+
+    assert_eq!(restored.account_history(1), history);
+    assert_eq!(restored.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn ledger_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<crate::ledger::Ledger>();
+}
+
+#[test]
+fn merge_ledgers_with_disjoint_clients() -> Result<()> {
+    let handles: Vec<_> = [(1, 1), (2, 2)]
+        .into_iter()
+        .map(|(client, id)| {
+            std::thread::spawn(move || {
+                let mut ledger = new_ledger();
+                ledger.process_tx(deposit_for(client, id, dec!(5.0)))?;
+                Ok::<_, Error>(ledger)
+            })
+        })
+        .collect();
+    let mut ledgers = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("worker panicked"));
+    let mut ledger = ledgers.next().expect("first ledger")?;
+
+    for other in ledgers {
+        ledger.merge(other?)?;
+    }
+
+    assert_eq!(ledger.accounts_summary().len(), 2);
+    assert_eq!(
+        get_account(&ledger, 2).map(|acct| acct.total),
+        Some(dec!(5.0))
+    );
+    assert_eq!(ledger.tx_log.len(), 2);
+
+    // Disputes reach deposits that came from the merged ledger
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Dispute, 2, None)
+    })?;
+
+    assert_eq!(
+        get_account(&ledger, 2).map(|acct| acct.held),
+        Some(dec!(5.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_ledgers_with_overlapping_clients() -> Result<()> {
+    let mut first = new_ledger();
+    first.process_tx(deposit_for(1, 1, dec!(5.0)))?;
+    first.process_tx(deposit_for(2, 2, dec!(1.0)))?;
+
+    let mut second = new_ledger();
+    second.process_tx(deposit_for(1, 3, dec!(2.5)))?;
+    second.process_tx(Transaction {
+        client: 1,
+        ..tx(TransactionType::Dispute, 3, None)
+    })?;
+    second.process_tx(Transaction {
+        client: 1,
+        ..tx(TransactionType::Chargeback, 3, None)
+    })?;
+
+    first.merge(second)?;
+
+    let account = get_account(&first, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.total, dec!(5.0));
+    assert!(account.locked);
+    assert_eq!(first.accounts_summary().len(), 2);
+    assert_eq!(first.deposits.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn merge_rejects_transactions_logged_twice() -> Result<()> {
+    let mut first = new_ledger();
+    first.process_tx(deposit_for(1, 1, dec!(5.0)))?;
+
+    let mut second = new_ledger();
+    second.process_tx(deposit_for(2, 2, dec!(3.0)))?;
+    second.process_tx(deposit_for(1, 1, dec!(3.0)))?;
+
+    let result = first.merge(second);
+
+    assert!(matches!(
+        result,
+        Err(Error::DuplicateTransaction { tx }) if tx.client == 1 && tx.id == 1
+    ));
+    assert_eq!(first.accounts_summary().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn merge_accepts_an_id_reused_by_another_client() -> Result<()> {
+    let mut first = new_ledger();
+    first.process_tx(deposit_for(1, 1, dec!(5.0)))?;
+
+    let mut second = new_ledger();
+    second.process_tx(deposit_for(2, 1, dec!(3.0)))?;
+
+    first.merge(second)?;
+    first.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Dispute, 1, None)
+    })?;
+
+    assert_eq!(
+        get_account(&first, 1).map(|acct| acct.available),
+        Some(dec!(5.0))
+    );
+    assert_eq!(
+        get_account(&first, 2).map(|acct| acct.held),
+        Some(dec!(3.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_fails_when_balances_overflow() -> Result<()> {
+    let huge = Decimal::MAX - dec!(1);
+
+    let mut first = new_ledger();
+    first.process_tx(deposit_for(1, 1, huge))?;
+    first.process_tx(deposit_for(2, 2, dec!(1.0)))?;
+
+    let mut second = new_ledger();
+    second.process_tx(deposit_for(2, 3, dec!(1.0)))?;
+    second.process_tx(deposit_for(1, 4, huge))?;
+
+    let result = first.merge(second);
+
+    assert!(matches!(
+        result,
+        Err(Error::MergeOverflow { client: Some(1) })
+    ));
+
+    // Nothing was merged, not even the client that did not overflow
+    assert_eq!(
+        get_account(&first, 2).map(|acct| acct.total),
+        Some(dec!(1.0))
+    );
+    assert_eq!(first.tx_log.len(), 2);
+
+    Ok(())
+}
+
+fn transfer(id: TransactionId, to: ClientId, amount: Decimal) -> Transaction {
+    Transaction {
+        to: Some(to),
+        ..tx(TransactionType::Transfer, id, Some(amount))
+    }
+}
+
+#[test]
+fn process_tx_transfer_moves_funds() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(transfer(2, 2, dec!(4.0)))?;
+
+    let source = get_account(&ledger, 1).expect("expected account for client.");
+    let dest = get_account(&ledger, 2).expect("expected account for client.");
+
+    assert_eq!(source.available, dec!(6.0));
+    assert_eq!(source.total, dec!(6.0));
+    assert_eq!(dest.available, dec!(4.0));
+    assert_eq!(dest.total, dec!(4.0));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    let result = ledger.process_tx(transfer(3, 1, dec!(1.0)));
+
+    assert!(matches!(result, Err(Error::InvalidTransfer { tx: _ })));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_transfer_insufficient_funds() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let result = ledger.process_tx(transfer(2, 2, dec!(10.5)));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let source = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(source.available, dec!(10.0));
+    assert!(get_account(&ledger, 2).is_none());
+
+    Ok(())
+}
+
+/// Ledger whose client 1 was locked by charging back deposit 1.
+fn locked_ledger(lock_policy: LockPolicy) -> Result<Ledger> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        lock_policy,
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    Ok(ledger)
+}
+
+#[test]
+fn deposit_into_locked_account_follows_lock_policy() -> Result<()> {
+    let mut frozen = locked_ledger(LockPolicy::FreezeAll)?;
+    let result = frozen.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))));
+
+    assert!(matches!(result, Err(Error::LockedAccount { tx: _ })));
+    assert_eq!(
+        get_account(&frozen, 1).map(|acct| acct.total),
+        Some(dec!(0.0))
+    );
+
+    let mut open = locked_ledger(LockPolicy::BlockWithdrawalsOnly)?;
+    open.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+
+    let account = get_account(&open, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.total, dec!(5.0));
+    assert!(account.locked);
+
+    Ok(())
+}
+
+#[test]
+fn block_withdrawals_only_still_rejects_debits_and_disputes() -> Result<()> {
+    let mut ledger = locked_ledger(LockPolicy::BlockWithdrawalsOnly)?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+
+    let withdrawal = ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(dec!(1.0))));
+    let dispute = ledger.process_tx(tx(TransactionType::Dispute, 2, None));
+
+    assert!(matches!(withdrawal, Err(Error::LockedAccount { tx: _ })));
+    assert!(matches!(dispute, Err(Error::LockedAccount { tx: _ })));
+
+    // Incoming transfers are credited like deposits
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Deposit, 4, Some(dec!(3.0)))
+    })?;
+    ledger.process_tx(Transaction {
+        client: 2,
+        to: Some(1),
+        ..tx(TransactionType::Transfer, 5, Some(dec!(3.0)))
+    })?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.available),
+        Some(dec!(8.0))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn chargeback_reversal_recredits_and_unlocks() -> Result<()> {
+    let mut ledger = locked_ledger(LockPolicy::FreezeAll)?;
+
+    // Merging is how a client ends up with a second chargeback
+    let mut other = new_ledger();
+    other.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    other.process_tx(tx(TransactionType::Dispute, 2, None))?;
+    other.process_tx(tx(TransactionType::Chargeback, 2, None))?;
+
+    ledger.merge(other)?;
+    ledger.process_tx(tx(TransactionType::ChargebackReversal, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert!(account.locked, "deposit 2 is still charged back");
+    assert_eq!(ledger.disputes.get(&(1, 1)), Some(&DisputeState::Resolved));
+
+    ledger.process_tx(tx(TransactionType::ChargebackReversal, 2, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(15.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(15.0));
+    assert!(!account.locked);
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn chargeback_reversal_requires_charged_back_transaction() -> Result<()> {
+    let mut ledger = new_ledger();
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let undisputed = ledger.process_tx(tx(TransactionType::ChargebackReversal, 1, None));
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    let disputed = ledger.process_tx(tx(TransactionType::ChargebackReversal, 1, None));
+
+    assert!(matches!(undisputed, Err(Error::NotChargedBack { tx: _ })));
+    assert!(matches!(disputed, Err(Error::NotChargedBack { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_transfer_to_locked_account() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Deposit, 2, Some(dec!(1.0)))
+    })?;
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Dispute, 2, None)
+    })?;
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Chargeback, 2, None)
+    })?;
+
+    let result = ledger.process_tx(transfer(3, 2, dec!(4.0)));
+
+    assert!(matches!(result, Err(Error::LockedAccount { tx: _ })));
+
+    let source = get_account(&ledger, 1).expect("expected account for client.");
+    let dest = get_account(&ledger, 2).expect("expected account for client.");
+
+    assert_eq!(source.available, dec!(10.0));
+    assert_eq!(source.total, dec!(10.0));
+    assert_eq!(dest.total, dec!(0.0));
+
+    Ok(())
+}
+
+#[test]
+fn dispute_expires_after_transactions() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        dispute_expiry: Some(DisputeExpiry::Transactions(2)),
+        auto_expire_disputes: true,
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(1.0))))?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.held),
+        Some(dec!(10.0))
+    );
+
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(1.0))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(12.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(ledger.open_disputes.get(&1), Some(&0));
+
+    let expired = ledger
+        .account_history(1)
+        .into_iter()
+        .find(|tx| matches!(tx.r#type, TransactionType::Resolve))
+        .expect("expected the expiry to be logged");
+
+    assert_eq!(expired.memo.as_deref(), Some("dispute expired"));
+
+    // The deposit can be disputed again
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    Ok(())
+}
+
+#[test]
+fn dispute_resolved_before_expiry_is_left_alone() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        dispute_expiry: Some(DisputeExpiry::Elapsed(Duration::hours(1))),
+        ..LedgerConfig::default()
+    });
+    let at = |minutes: i64| {
+        DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .map(|ts| ts.to_utc() + Duration::minutes(minutes))
+            .ok()
+    };
+
+    for (id, minutes) in [(1, 0), (2, 10)] {
+        ledger.process_tx(Transaction {
+            timestamp: at(minutes),
+            ..tx(TransactionType::Deposit, id, Some(dec!(5.0)))
+        })?;
+        ledger.process_tx(Transaction {
+            timestamp: at(minutes),
+            ..tx(TransactionType::Dispute, id, None)
+        })?;
+    }
+
+    ledger.process_tx(Transaction {
+        timestamp: at(30),
+        ..tx(TransactionType::Resolve, 2, None)
+    })?;
+    ledger.process_tx(Transaction {
+        timestamp: at(65),
+        ..tx(TransactionType::Deposit, 3, Some(dec!(1.0)))
+    })?;
+
+    // Expiry only runs when asked to without `auto_expire_disputes`
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.held),
+        Some(dec!(5.0))
+    );
+    assert_eq!(ledger.expire_disputes(), vec![(1, 1)]);
+    assert!(ledger.expire_disputes().is_empty());
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(11.0));
+    assert_eq!(account.held, dec!(0.0));
+
+    Ok(())
+}
+
+#[test]
+fn out_of_order_timestamps_are_rejected() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        reject_out_of_order: true,
+        ..LedgerConfig::default()
+    });
+    let at = |minutes: i64| {
+        DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .map(|ts| ts.to_utc() + Duration::minutes(minutes))
+            .ok()
+    };
+
+    for (id, minutes) in [(1, 0), (2, 5), (3, 5)] {
+        ledger.process_tx(Transaction {
+            timestamp: at(minutes),
+            ..tx(TransactionType::Deposit, id, Some(dec!(1.0)))
+        })?;
+    }
+
+    let result = ledger.process_tx(Transaction {
+        timestamp: at(4),
+        ..tx(TransactionType::Deposit, 4, Some(dec!(1.0)))
+    });
+
+    assert!(matches!(result, Err(Error::OutOfOrder { tx: _ })));
+
+    // Rows without a timestamp are not ordered against the others
+    ledger.process_tx(tx(TransactionType::Deposit, 5, Some(dec!(1.0))))?;
+    ledger.process_tx(Transaction {
+        timestamp: at(6),
+        ..tx(TransactionType::Deposit, 6, Some(dec!(1.0)))
+    })?;
+
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(dec!(5.0))
+    );
+
+    // Off by default
+    let mut ledger = new_ledger();
+    ledger.process_tx(Transaction {
+        timestamp: at(5),
+        ..tx(TransactionType::Deposit, 1, Some(dec!(1.0)))
+    })?;
+    ledger.process_tx(Transaction {
+        timestamp: at(0),
+        ..tx(TransactionType::Deposit, 2, Some(dec!(1.0)))
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn deposit_hold_delays_available_funds() -> Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        deposit_hold: Some(Duration::hours(1)),
+        ..LedgerConfig::default()
+    });
+    let at = |minutes: i64| {
+        DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .map(|ts| ts.to_utc() + Duration::minutes(minutes))
+            .ok()
+    };
+
+    ledger.process_tx(Transaction {
+        timestamp: at(0),
+        ..tx(TransactionType::Deposit, 1, Some(dec!(10.0)))
+    })?;
+
+    let result = ledger.process_tx(Transaction {
+        timestamp: at(30),
+        ..tx(TransactionType::Withdrawal, 2, Some(dec!(5.0)))
+    });
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+
+    ledger.process_tx(Transaction {
+        timestamp: at(60),
+        ..tx(TransactionType::Withdrawal, 3, Some(dec!(5.0)))
+    })?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(5.0));
+
+    Ok(())
+}
+
+#[test]
+fn close_account_requires_zero_balances() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let result = ledger.close_account(1);
+
+    assert!(matches!(
+        result,
+        Err(Error::AccountNotClosable { client: 1 })
+    ));
+    assert!(get_account(&ledger, 1).is_some());
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(10.0))))?;
+
+    let account = ledger.close_account(1)?;
+
+    assert_eq!(account.id, Some(1));
+    assert!(account.total.is_zero());
+    assert!(get_account(&ledger, 1).is_none());
+
+    let result = ledger.close_account(1);
+
+    assert!(matches!(result, Err(Error::UnknownClient { client: 1 })));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_mirrored_withdrawal_dispute_is_rejected() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(10.0))))?;
+
+    // Only deposits are disputable, so the funds of a deposit and the
+    // withdrawal spending them can never be held twice.
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 2, None));
+
+    assert!(matches!(
+        result,
+        Err(Error::WithdrawalNotDisputable { tx: _ })
+    ));
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 1, None));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(0.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_withdrawal_chargeback_is_rejected() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(4.0))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 2, None));
+
+    assert!(matches!(
+        result,
+        Err(Error::WithdrawalNotDisputable { tx: _ })
+    ));
+
+    let result = ledger.process_tx(tx(TransactionType::Chargeback, 2, None));
+
+    assert!(matches!(result, Err(Error::DisputeTxNotFound { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(6.0));
+    assert_eq!(account.total, dec!(6.0));
+    assert!(!account.locked);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_deposit_and_withdrawal() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(3.0))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 2, None));
+
+    assert!(matches!(
+        result,
+        Err(Error::WithdrawalNotDisputable { tx: _ })
+    ));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(7.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(7.0));
+
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 3, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(7.0));
+    assert_eq!(account.held, dec!(5.0));
+    assert_eq!(account.total, dec!(12.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_first_withdrawal_policy() -> Result<()> {
+    let withdrawal = tx(TransactionType::Withdrawal, 1, Some(dec!(5.0)));
+
+    let mut ledger = new_ledger();
+    let result = ledger.process_tx(withdrawal.clone());
+
+    assert!(matches!(result, Err(Error::AccountNotFound { tx: _ })));
+    assert!(get_account(&ledger, 1).is_none());
+
+    let mut ledger = ledger_with_config(LedgerConfig {
+        first_tx_withdrawal_policy: FirstWithdrawalPolicy::OpenThenFail,
+        ..LedgerConfig::default()
+    });
+    let result = ledger.process_tx(withdrawal);
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+    assert_eq!(get_account(&ledger, 1), Some(Account::new(1)));
+
+    Ok(())
+}
+
+#[test]
+fn balance_reports_single_account() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(4.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+
+    assert_eq!(
+        ledger.balance(1),
+        Some(Balance {
+            available: dec!(10.0),
+            held: dec!(4.0),
+            total: dec!(14.0),
+            locked: false,
+        })
+    );
+    assert_eq!(ledger.balance(2), None);
+
+    Ok(())
+}
+
+#[test]
+fn balances_map_has_every_client() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Deposit, 2, Some(dec!(2.5)))
+    })?;
+
+    let balances = ledger.balances_map();
+
+    assert_eq!(balances.len(), 2);
+    assert_eq!(
+        balances.get(&1),
+        Some(&Balance {
+            available: dec!(0.0),
+            held: dec!(10.0),
+            total: dec!(10.0),
+            locked: false,
+        })
+    );
+    assert_eq!(
+        balances.get(&2),
+        Some(&Balance {
+            available: dec!(2.5),
+            held: dec!(0.0),
+            total: dec!(2.5),
+            locked: false,
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_processed_sees_every_outcome() -> Result<()> {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut ledger = ledger_with_config(LedgerConfig {
+        strict_support: true,
+        ..LedgerConfig::default()
+    });
+
+    let shared = Arc::clone(&seen);
+    ledger.on_processed(move |tx, result| {
+        let outcome = result.as_ref().map_err(Error::kind).copied();
+        shared
+            .lock()
+            .expect("seen lock")
+            .push((tx.r#type.clone(), tx.id, outcome));
+    });
+
+    let txs = vec![
+        tx(TransactionType::Deposit, 1, Some(dec!(10.0))),
+        tx(TransactionType::Deposit, 2, Some(dec!(5.0))),
+        tx(TransactionType::Deposit, 2, Some(dec!(5.0))),
+        tx(TransactionType::Dispute, 1, Some(dec!(10.0))),
+        tx(TransactionType::Withdrawal, 3, Some(dec!(20.0))),
+    ];
+    ledger
+        .process_transactions(futures::stream::iter(txs.into_iter().map(Ok)))
+        .await?;
+
+    assert_eq!(
+        *seen.lock().expect("seen lock"),
+        vec![
+            (TransactionType::Deposit, 1, Ok(())),
+            (TransactionType::Deposit, 2, Ok(())),
+            (TransactionType::Deposit, 2, Err("DuplicateTransaction")),
+            (
+                TransactionType::Dispute,
+                1,
+                Err("SupportTransactionWithAmount")
+            ),
+            (TransactionType::Withdrawal, 3, Err("InsufficientFunds")),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn events_follow_dispute_and_chargeback() -> Result<()> {
+    struct Shared(Arc<Mutex<Vec<LedgerEvent>>>);
+
+    impl EventSink for Shared {
+        fn emit(&mut self, event: LedgerEvent) {
+            self.0.lock().expect("events lock").push(event);
+        }
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut ledger = new_ledger();
+    ledger.set_event_sink(Box::new(Shared(Arc::clone(&events))));
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    // Rejected transactions change nothing and emit nothing
+    let _ = ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(1.0))));
+
+    assert_eq!(
+        *events.lock().expect("events lock"),
+        vec![
+            LedgerEvent::Deposited {
+                client: 1,
+                tx: 1,
+                amount: dec!(10.0),
+            },
+            LedgerEvent::FundsHeld {
+                client: 1,
+                tx: 1,
+                amount: dec!(10.0),
+            },
+            LedgerEvent::ChargedBack {
+                client: 1,
+                tx: 1,
+                amount: dec!(10.0),
+            },
+            LedgerEvent::AccountLocked { client: 1 },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn apply_from_iter_counts_applied_transactions() -> Result<()> {
+    let txs = || {
+        vec![
+            tx(TransactionType::Deposit, 1, Some(dec!(10.0))),
+            tx(TransactionType::Withdrawal, 2, Some(dec!(20.0))),
+            tx(TransactionType::Deposit, 3, Some(dec!(5.0))),
+        ]
+    };
+
+    let mut ledger = new_ledger();
+
+    assert_eq!(ledger.apply_from_iter(txs(), false).ok(), Some(2));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(dec!(15.0))
+    );
+
+    let mut ledger = new_ledger();
+    let result = ledger.apply_from_iter(txs(), true);
+
+    assert!(matches!(result, Err((1, Error::InsufficientFunds { tx })) if tx.id == 2));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(dec!(10.0))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn huge_deposits_overflow_instead_of_panicking() -> Result<()> {
+    let huge = Decimal::MAX - dec!(1);
+
+    let mut ledger = new_ledger();
+    let txs = [
+        tx(TransactionType::Deposit, 1, Some(huge)),
+        tx(TransactionType::Deposit, 2, Some(huge)),
+        tx(TransactionType::Deposit, 3, Some(dec!(1.0))),
+    ];
+    let report = ledger
+        .process_transactions(futures::stream::iter(txs.map(Ok)))
+        .await?;
+
+    assert_eq!(report.applied, 2);
+    assert_eq!(report.errors, BTreeMap::from([("AmountOverflow", 1)]));
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.total),
+        Some(Decimal::MAX)
+    );
+
+    let mut ledger = ledger_with_config(LedgerConfig {
+        overdraft_limit: Decimal::MAX,
+        ..LedgerConfig::default()
+    });
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(1.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(huge)))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Withdrawal, 3, Some(huge)));
+
+    assert!(matches!(result, Err(Error::AmountOverflow { tx: _ })));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_adjustments_credit_and_debit() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Adjustment, 2, Some(dec!(0.5))))?;
+    ledger.process_tx(tx(TransactionType::Adjustment, 3, Some(dec!(-2.5))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(8.0));
+    assert_eq!(account.total, dec!(8.0));
+
+    let result = ledger.process_tx(tx(TransactionType::Adjustment, 4, Some(dec!(-8.5))));
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+
+    let result = ledger.process_tx(tx(TransactionType::Adjustment, 5, Some(Decimal::MAX)));
+
+    assert!(matches!(result, Err(Error::AmountOverflow { tx: _ })));
+
+    ledger.process_tx(tx(TransactionType::Deposit, 6, Some(dec!(1.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 6, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 6, None))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Adjustment, 7, Some(dec!(1.0))));
+
+    assert!(matches!(result, Err(Error::LockedAccount { tx: _ })));
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 2, None));
+
+    assert!(matches!(result, Err(Error::TransactionNotFound { tx: _ })));
+
+    Ok(())
+}
+
+#[test]
+fn verify_replay_flags_corrupted_balances() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.seed_opening(vec![Account {
+        id: Some(2),
+        available: dec!(3.0),
+        held: dec!(1.0),
+        total: dec!(4.0),
+        locked: false,
+    }])?;
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 2, Some(dec!(5.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(2.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 4, Some(dec!(1.5))))?;
+    ledger.process_tx(tx(TransactionType::Adjustment, 5, Some(dec!(-0.5))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 3, None))?;
+    ledger.process_tx(tx(TransactionType::Resolve, 3, None))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 2, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 2, None))?;
+
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    let mut restored = restore_ledger(ledger.snapshot());
+
+    assert_eq!(restored.verify_replay(), Ok(()));
+
+    if let Some(acct) = restored.accounts.get_mut(&1) {
+        acct.available += dec!(1.0);
+        acct.total += dec!(1.0);
+    }
+
+    if let Some(acct) = restored.accounts.get_mut(&2) {
+        acct.held += dec!(0.1);
+    }
+
+    assert_eq!(restored.verify_replay(), Err(vec![1, 2]));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_two_accounts() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(1.0))))?;
+
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Deposit, 2, Some(dec!(2.0)))
+    })?;
+
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(2.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 4, Some(dec!(1.5))))?;
+
+    let result = ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Withdrawal, 5, Some(dec!(3.0)))
+    });
+
+    assert!(result.is_err(), "should fail due to insufficient funds");
+
+    let account_1 = get_account(&ledger, 1).expect("expected account for client.");
+    let account_2 = get_account(&ledger, 2).expect("expected account for client.");
+
+    assert_eq!(
+        account_1,
+        Account {
+            id: Some(1),
+            available: dec!(1.5),
+            held: dec!(0.0),
+            locked: false,
+            total: dec!(1.5),
+        }
+    );
+
+    assert_eq!(
+        account_2,
+        Account {
+            id: Some(2),
+            available: dec!(2.0),
+            held: dec!(0.0),
+            locked: false,
+            total: dec!(2.0),
+        }
+    );
+
+    assert_eq!(ledger.tx_log.len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_tx_not_found() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let tx = tx(TransactionType::Dispute, 3, None);
+    let result = ledger.process_tx(tx.clone());
+
+    assert!(result.is_err());
+    assert!(matches!(result, Err(Error::TransactionNotFound { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_with_amount_under_strict_support() -> Result<()> {
+    let dispute = tx(TransactionType::Dispute, 1, Some(dec!(10.0)));
+
+    for strict_support in [false, true] {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            strict_support,
+            ..LedgerConfig::default()
+        });
+
+        ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+        let result = ledger.process_tx(dispute.clone());
+        let account = get_account(&ledger, 1).expect("expected account for client.");
+
+        if strict_support {
+            assert!(matches!(
+                result,
+                Err(Error::SupportTransactionWithAmount { tx: _ })
+            ));
+            assert_eq!(account.held, dec!(0.0));
+        } else {
+            assert!(result.is_ok());
+            assert_eq!(account.held, dec!(10.0));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_resolve() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    ledger.process_tx(tx(TransactionType::Resolve, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn locked_accounts_lists_charged_back_clients() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    for client in [3, 1, 2] {
+        ledger.process_tx(deposit_for(client, client.into(), dec!(5.0)))?;
+    }
+
+    for r#type in [TransactionType::Dispute, TransactionType::Chargeback] {
+        ledger.process_tx(Transaction {
+            client: 2,
+            ..tx(r#type, 2, None)
+        })?;
+    }
+
+    assert_eq!(ledger.locked_accounts(), vec![2]);
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_partial_dispute_chargeback() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, Some(dec!(4.0))))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(6.0));
+    assert_eq!(account.held, dec!(4.0));
+
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(account.locked);
+    assert_eq!(account.available, dec!(6.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(6.0));
+    assert!(ledger.verify_replay().is_ok());
+
+    let restored = restore_ledger(ledger.snapshot());
+
+    assert!(restored.verify_replay().is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_partial_dispute_resolve() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, Some(dec!(2.5))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(7.5))))?;
+    ledger.process_tx(tx(TransactionType::Resolve, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(2.5));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(2.5));
+    assert!(ledger.disputed_amounts.is_empty());
+
+    // Disputing again without an amount holds the whole deposit
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.held, dec!(10.0));
+    assert_eq!(account.available, dec!(2.5));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_partial_dispute_bounded_by_deposit() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 1, Some(dec!(10.01))));
+    assert!(matches!(
+        result,
+        Err(Error::DisputeExceedsDeposit { tx: _ })
+    ));
+
+    let result = ledger.process_tx(tx(TransactionType::Dispute, 1, Some(dec!(0.0))));
+    assert!(matches!(result, Err(Error::InvalidAmount { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.available, dec!(10.0));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_dispute_deposit_chargeback() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(100.0))))?;
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(account.locked);
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(0.0));
+    assert_eq!(ledger.tx_log.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn amounts_above_the_limit_are_rejected() -> Result<()> {
+    let limit = dec!(1000000000000);
+    let just_over = limit + dec!(0.0001);
+
+    let mut ledger = ledger_with_config(LedgerConfig {
+        max_amount: Some(limit),
+        ..LedgerConfig::default()
+    });
+
+    let report = ledger
+        .process_transactions(futures::stream::iter([
+            Ok(tx(TransactionType::Deposit, 1, Some(limit))),
+            Ok(tx(TransactionType::Deposit, 2, Some(just_over))),
+            Ok(tx(TransactionType::Deposit, 3, Some(dec!(5.0)))),
+        ]))
+        .await?;
+
+    assert_eq!((report.applied, report.rejected), (2, 1));
+
+    let over = ledger.process_tx(tx(TransactionType::Withdrawal, 4, Some(just_over)));
+
+    assert!(matches!(
+        over,
+        Err(Error::AmountExceedsLimit { tx, limit: l }) if tx.id == 4 && l == limit
+    ));
+
+    ledger.process_tx(tx(TransactionType::Withdrawal, 5, Some(limit)))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.total, dec!(5.0));
+
+    let mut ledger = ledger_with_config(LedgerConfig {
+        max_amount: Some(limit),
+        ..LedgerConfig::default()
+    });
+    let over = ledger.process_tx(tx(TransactionType::Deposit, 1, Some(just_over)));
+
+    assert!(matches!(
+        over,
+        Err(Error::AmountExceedsLimit { tx: _, limit: _ })
+    ));
+    assert!(ledger.tx_log.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_deposit_rejections() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    let result = ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(-1.0))));
+
+    assert!(matches!(result, Err(Error::InvalidAmount { tx: _ })));
+
+    let result = ledger.process_tx(tx(TransactionType::Deposit, 2, None));
+
+    assert!(matches!(
+        result,
+        Err(Error::DomesticTransactionMissingAmount { tx: _ })
+    ));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.total, dec!(0.0));
+    assert!(ledger.tx_log.is_empty());
+
+    let deposit = tx(TransactionType::Deposit, 3, Some(dec!(10.0)));
+
+    ledger.process_tx(deposit.clone())?;
+    ledger.accounts.get_mut(&1).unwrap().locked = true;
+
+    let result = ledger.process_tx(deposit);
+
+    assert!(matches!(result, Err(Error::DuplicateTransaction { tx: _ })));
+
+    let result = ledger.process_tx(tx(TransactionType::Deposit, 4, Some(dec!(10.0))));
+
+    assert!(matches!(result, Err(Error::LockedAccount { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.total, dec!(10.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn risk_report_combines_disputes_and_locks() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    for (client, id) in [(1, 1), (1, 2), (2, 3)] {
+        ledger.process_tx(Transaction {
+            client,
+            ..tx(TransactionType::Deposit, id, Some(dec!(5.0)))
+        })?;
+    }
+
+    for (r#type, client, id) in [
+        (TransactionType::Dispute, 1, 1),
+        (TransactionType::Dispute, 1, 2),
+        (TransactionType::Resolve, 1, 2),
+        (TransactionType::Dispute, 2, 3),
+        (TransactionType::Chargeback, 2, 3),
+    ] {
+        ledger.process_tx(Transaction {
+            client,
+            ..tx(r#type, id, None)
+        })?;
+    }
+
+    assert_eq!(
+        ledger.risk_report(),
+        vec![
+            RiskEntry {
+                client: 1,
+                held: dec!(5.0),
+                open_disputes: 1,
+                locked: false,
+                locked_by: None,
+            },
+            RiskEntry {
+                client: 2,
+                held: dec!(0.0),
+                open_disputes: 0,
+                locked: true,
+                locked_by: Some(3),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn risk_report_names_the_lowest_chargeback_after_a_merge() -> Result<()> {
+    let mut merged = new_ledger();
+
+    for id in [4, 2, 5, 3, 6] {
+        let mut ledger = new_ledger();
+        ledger.process_tx(tx(TransactionType::Deposit, id, Some(dec!(1.0))))?;
+        ledger.process_tx(tx(TransactionType::Dispute, id, None))?;
+        ledger.process_tx(tx(TransactionType::Chargeback, id, None))?;
+        merged.merge(ledger)?;
+    }
+
+    let report = merged.risk_report();
+
+    assert_eq!(report.len(), 1);
+    assert!(report[0].locked);
+    assert_eq!(report[0].locked_by, Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn process_tx_in_effective_date_order() -> Result<()> {
+    let date = |day| NaiveDate::from_ymd_opt(2024, 1, day);
+    let txs = vec![
+        Transaction {
+            effective: date(2),
+            ..tx(TransactionType::Withdrawal, 2, Some(dec!(5.0)))
+        },
+        Transaction {
+            effective: date(1),
+            ..tx(TransactionType::Deposit, 1, Some(dec!(10.0)))
+        },
+    ];
+
+    let mut in_file_order = new_ledger();
+
+    for tx in txs.iter().cloned() {
+        let _ = in_file_order.process_tx(tx);
+    }
+
+    let mut sorted = txs.clone();
+    sort_by_effective_date(&mut sorted);
+
+    let mut in_effective_order = new_ledger();
+
+    for tx in sorted {
+        in_effective_order.process_tx(tx)?;
+    }
+
+    let account = get_account(&in_file_order, 1).expect("expected account for client.");
+
+    assert_eq!(account.total, dec!(10.0));
+
+    let account = get_account(&in_effective_order, 1).expect("expected account for client.");
+
+    assert_eq!(account.total, dec!(5.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn process_reader_summarizes_fixture() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+    let report = ledger
+        .process_reader(CsvReader::new("fixtures/sample_01.csv")?)
+        .await?;
+
+    assert_eq!(
+        (report.applied, report.rejected, report.unreadable),
+        (4, 1, 0)
+    );
+
+    let mut ledger = new_ledger();
+    let report = ledger
+        .process_reader(CsvReader::new("fixtures/sample_04.csv")?)
+        .await?;
+
+    assert_eq!(
+        (report.applied, report.rejected, report.unreadable),
+        (1, 0, 1)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn process_stream_yields_each_outcome() {
+    let txs = vec![
+        Ok(tx(TransactionType::Deposit, 1, Some(dec!(5.0)))),
+        Err(anyhow::anyhow!("bad row")),
+        Ok(tx(TransactionType::Withdrawal, 2, Some(dec!(10.0)))),
+        Ok(tx(TransactionType::Withdrawal, 3, Some(dec!(2.0)))),
+    ];
+    let mut ledger = new_ledger();
+    let results: Vec<Result<()>> = ledger
+        .process_stream(futures::stream::iter(txs))
+        .collect()
+        .await;
+
+    assert_eq!(results.len(), 4);
+    assert!(results[0].is_ok());
+    assert!(matches!(
+        &results[1],
+        Err(Error::UnreadableTransaction { reason }) if reason == "bad row"
+    ));
+    assert!(matches!(results[2], Err(Error::InsufficientFunds { .. })));
+    assert!(results[3].is_ok());
+    assert_eq!(
+        get_account(&ledger, 1).map(|acct| acct.available),
+        Some(dec!(3.0))
+    );
+}
+
+#[tokio::test]
+async fn process_stream_stops_when_dropped() {
+    let txs = (1..=3).map(|id| Ok(tx(TransactionType::Deposit, id, Some(dec!(1.0)))));
+    let mut ledger = new_ledger();
+    let applied = ledger
+        .process_stream(futures::stream::iter(txs))
+        .take(2)
+        .count()
+        .await;
+
+    assert_eq!(applied, 2);
+    assert_eq!(ledger.tx_log.len(), 2);
+}
+
+#[tokio::test]
+async fn report_counts_types_and_errors() -> anyhow::Result<()> {
+    let txs = vec![
+        Ok(tx(TransactionType::Deposit, 1, Some(dec!(5.0)))),
+        Ok(tx(TransactionType::Deposit, 1, Some(dec!(5.0)))),
+        Ok(tx(TransactionType::Withdrawal, 2, Some(dec!(2.0)))),
+        Ok(tx(TransactionType::Withdrawal, 3, Some(dec!(10.0)))),
+        Err(anyhow::anyhow!("bad row")),
+        Ok(tx(TransactionType::Deposit, 4, Some(dec!(2.0)))),
+        Ok(tx(TransactionType::Dispute, 1, None)),
+        Ok(tx(TransactionType::Resolve, 1, None)),
+        Ok(tx(TransactionType::Chargeback, 1, None)),
+    ];
+    let mut ledger = new_ledger();
+    let report = ledger
+        .process_transactions(futures::stream::iter(txs))
+        .await?;
+
+    assert_eq!(report.applied, 5);
+    assert_eq!(report.rejected, 3);
+    assert_eq!(report.unreadable, 1);
+    assert_eq!(
+        report.by_type,
+        BTreeMap::from([
+            (TransactionType::Deposit, 3),
+            (TransactionType::Withdrawal, 2),
+            (TransactionType::Dispute, 1),
+            (TransactionType::Resolve, 1),
+            (TransactionType::Chargeback, 1),
+        ])
+    );
+    assert_eq!(
+        report.errors,
+        BTreeMap::from([
+            ("DisputeTxNotFound", 1),
+            ("DuplicateTransaction", 1),
+            ("InsufficientFunds", 1),
+        ])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn process_reader_stops_at_first_error_when_strict() -> anyhow::Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        strict: true,
+        ..LedgerConfig::default()
+    });
+
+    let result = ledger
+        .process_reader(CsvReader::new("fixtures/corrupt.csv")?)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::UnreadableTransaction { reason }) if reason.contains("not-a-number")
+    ));
+
+    let txs = vec![
+        tx(TransactionType::Deposit, 1, Some(dec!(1.0))),
+        tx(TransactionType::Withdrawal, 2, Some(dec!(5.0))),
+        tx(TransactionType::Deposit, 3, Some(dec!(1.0))),
+    ];
+    let mut ledger = ledger_with_config(LedgerConfig {
+        strict: true,
+        ..LedgerConfig::default()
+    });
+
+    let result = ledger
+        .process_transactions(futures::stream::iter(txs.into_iter().map(Ok)))
+        .await;
+
+    assert!(matches!(result, Err(Error::InsufficientFunds { tx: _ })));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn process_reader_aborts_past_max_errors() -> anyhow::Result<()> {
+    let mut ledger = ledger_with_config(LedgerConfig {
+        max_errors: Some(2),
+        ..LedgerConfig::default()
+    });
+    let result = ledger
+        .process_reader(CsvReader::new("fixtures/corrupt.csv")?)
+        .await;
+
+    let err = result.expect_err("expected the run to abort");
+
+    assert!(matches!(err, Error::TooManyErrors { errors: 3 }));
+    assert_eq!(
+        err.to_string(),
+        "Aborted after 3 read or processing errors, the input is too corrupt to trust"
+    );
+
+    // Rows after the threshold was crossed are not applied
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.total, dec!(1.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dense_accounts_match_map_accounts() -> anyhow::Result<()> {
+    for n in 1..=6 {
+        let path = format!("fixtures/sample_{n:02}.csv");
+        let mut summaries = Vec::new();
+
+        for dense_accounts in [0, 2, 1024] {
+            let mut ledger = ledger_with_config(LedgerConfig {
+                dense_accounts,
+                ..LedgerConfig::default()
+            });
+            ledger.process_reader(CsvReader::new(&path)?).await?;
+
+            let mut summary = ledger.accounts_summary();
+            summary.sort_by_key(|acct| acct.id);
+            summaries.push(summary);
+        }
+
+        assert_eq!(summaries[0], summaries[1], "{path}");
+        assert_eq!(summaries[0], summaries[2], "{path}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn memos_are_kept_in_the_log() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+    ledger
+        .process_reader(CsvReader::new("fixtures/memos.csv")?)
+        .await?;
+
+    let dispute = ledger
+        .find_tx(|t| matches!(t.r#type, TransactionType::Dispute))
+        .expect("expected the dispute in the log");
+
+    assert_eq!(
+        dispute.memo.as_deref(),
+        Some("card reported stolen, ticket 4411")
+    );
+
+    let memos: Vec<Option<&str>> = ledger
+        .account_history(1)
+        .iter()
+        .map(|tx| tx.memo.as_deref())
+        .collect();
+
+    assert_eq!(
+        memos,
+        vec![
+            Some("payroll"),
+            None,
+            Some("card reported stolen, ticket 4411")
+        ]
+    );
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert_eq!(account.available, dec!(5.0));
+    assert_eq!(account.held, dec!(10.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn estimate_memory_scales_with_counts() -> anyhow::Result<()> {
+    let estimate =
+        crate::ledger::Ledger::estimate_memory(CsvReader::new("fixtures/sample_01.csv")?).await;
+
+    assert_eq!(estimate.clients, 2);
+    assert_eq!(estimate.transactions, 5);
+    assert!(estimate.bytes > 0);
+    assert_eq!(estimate, MemoryEstimate::from_counts(2, 5));
+
+    let larger = MemoryEstimate::from_counts(2_000, 5_000_000);
+
+    assert!(larger.bytes > estimate.bytes * 100_000);
+    assert!(MemoryEstimate::from_counts(4, 10).bytes >= estimate.bytes);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn diff_reports_changed_clients_only() -> anyhow::Result<()> {
+    let mut before = new_ledger();
+    before
+        .process_reader(CsvReader::new("fixtures/diff_before.csv")?)
+        .await?;
+
+    let mut after = new_ledger();
+    after
+        .process_reader(CsvReader::new("fixtures/diff_after.csv")?)
+        .await?;
+
+    assert_eq!(
+        before.diff(&after),
+        vec![BalanceDiff {
+            client: 2,
+            available: dec!(-0.5),
+            held: dec!(0.0),
+            total: dec!(-0.5),
+            locked_before: false,
+            locked_after: false,
+        }]
+    );
+    assert!(before.diff(&before).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn finalize_and_stream_matches_batch_writer() -> anyhow::Result<()> {
+    let mut ledger = new_ledger();
+    ledger
+        .process_reader(CsvReader::new("fixtures/sample_01.csv")?)
+        .await?;
+
+    let mut batch = Vec::new();
+    let mut writer = CsvWriter::from_writer(&mut batch);
+
+    for acct in ledger.accounts_summary() {
+        writer.write(&acct)?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+
+    let mut streamed = Vec::new();
+    ledger.finalize_and_stream(&mut CsvWriter::from_writer(&mut streamed))?;
+
+    let sorted_lines = |buf: Vec<u8>| -> anyhow::Result<Vec<String>> {
+        let mut lines: Vec<String> = String::from_utf8(buf)?.lines().map(String::from).collect();
+        lines.sort();
+        Ok(lines)
+    };
+
+    assert_eq!(sorted_lines(streamed)?, sorted_lines(batch)?);
+
+    Ok(())
+}
+
+#[test]
+fn hot_accounts_cache_keeps_results_unchanged() -> Result<()> {
+    let txs: Vec<Transaction> = (0..200)
+        .map(|id| Transaction {
+            client: [1, 1, 2, 1, 3, 4, 1, 2][id as usize % 8],
+            ..tx(
+                if id % 3 == 0 {
+                    TransactionType::Withdrawal
+                } else {
+                    TransactionType::Deposit
+                },
+                id,
+                Some(dec!(2.0)),
+            )
+        })
+        .chain([1, 2].map(|id| tx(TransactionType::Dispute, id, None)))
+        .collect();
+
+    let run = |hot_accounts| {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            hot_accounts,
+            ..LedgerConfig::default()
+        });
+        let outcomes: Vec<bool> = txs
+            .iter()
+            .map(|tx| ledger.process_tx(tx.clone()).is_ok())
+            .collect();
+        let mut summary = ledger.accounts_summary();
+        summary.sort_by_key(|acct| acct.id);
+
+        (outcomes, summary)
+    };
+
+    assert_eq!(run(0), run(2));
+
+    Ok(())
+}
+
+#[test]
+fn changed_accounts_since_opening() -> Result<()> {
+    let opening: Vec<Account> = (1..=3)
+        .map(|id| Account {
+            available: dec!(10.0),
+            total: dec!(10.0),
+            ..Account::new(id)
+        })
+        .collect();
+
+    let mut baseline = new_ledger();
+    baseline.seed_opening(opening.clone())?;
+
+    let mut ledger = new_ledger();
+    ledger.seed_opening(opening)?;
+
+    ledger.process_tx(Transaction {
+        client: 2,
+        ..tx(TransactionType::Withdrawal, 1, Some(dec!(4.0)))
+    })?;
+
+    assert_eq!(
+        ledger.changed_accounts(&baseline),
+        vec![Account {
+            available: dec!(6.0),
+            total: dec!(6.0),
+            ..Account::new(2)
+        }]
+    );
+
+    Ok(())
+}
+
+/// Throughput check of the hot accounts cache on a Zipfian client
+/// distribution, run it with
+/// `cargo test --release -- --ignored --nocapture hot_accounts_throughput`.
+#[test]
+#[ignore]
+fn hot_accounts_throughput() -> Result<()> {
+    const CLIENTS: usize = 10_000;
+    const TXS: u32 = 1_000_000;
+
+    // Zipf(s = 1) cumulative distribution over the clients
+    let weights: Vec<f64> = (1..=CLIENTS).map(|rank| 1.0 / rank as f64).collect();
+    let norm: f64 = weights.iter().sum();
+    let cdf: Vec<f64> = weights
+        .iter()
+        .scan(0.0, |acc, w| {
+            *acc += w / norm;
+            Some(*acc)
+        })
+        .collect();
+
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let clients: Vec<ClientId> = (0..TXS)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let sample = (seed >> 11) as f64 / (1u64 << 53) as f64;
+            cdf.partition_point(|p| *p < sample).min(CLIENTS - 1) as ClientId
+        })
+        .collect();
+
+    for hot_accounts in [0, 4, 16] {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            hot_accounts,
+            ..LedgerConfig::default()
+        });
+        let started = std::time::Instant::now();
+
+        for (id, client) in clients.iter().enumerate() {
+            ledger.process_tx(Transaction {
+                client: *client,
+                ..tx(
+                    TransactionType::Deposit,
+                    id as TransactionId,
+                    Some(dec!(1.5)),
+                )
+            })?;
+        }
+
+        println!("hot_accounts={hot_accounts}: {:?}", started.elapsed());
+    }
+
+    Ok(())
+}
+
+/// Compares map and vector account storage over dense client ids, run
+/// it with `cargo test --release -- --ignored --nocapture
+/// dense_accounts_throughput`.
+#[test]
+#[ignore]
+fn dense_accounts_throughput() -> Result<()> {
+    const CLIENTS: TransactionId = 10_000;
+    const TXS: TransactionId = 1_000_000;
+
+    for dense_accounts in [0, CLIENTS as usize] {
+        let mut ledger = ledger_with_config(LedgerConfig {
+            dense_accounts,
+            ..LedgerConfig::default()
+        });
+        let started = std::time::Instant::now();
+
+        for id in 0..TXS {
+            ledger.process_tx(Transaction {
+                client: (id.wrapping_mul(7919) % CLIENTS) as ClientId,
+                ..tx(TransactionType::Deposit, id, Some(dec!(1.5)))
+            })?;
+        }
+
+        println!("dense_accounts={dense_accounts}: {:?}", started.elapsed());
+    }
+
+    Ok(())
+}
+
+/// Dispute lookups against a large log, run it with
+/// `cargo test --release -- --ignored --nocapture dispute_lookup_throughput`.
+#[test]
+#[ignore]
+fn dispute_lookup_throughput() -> Result<()> {
+    const DEPOSITS: u32 = 50_000;
+
+    let mut ledger = new_ledger();
+
+    for id in 0..DEPOSITS {
+        ledger.process_tx(tx(
+            TransactionType::Deposit,
+            id as TransactionId,
+            Some(dec!(1.5)),
+        ))?;
+    }
+
+    let started = std::time::Instant::now();
+
+    for id in 0..DEPOSITS {
+        ledger.process_tx(tx(TransactionType::Dispute, id as TransactionId, None))?;
+    }
+
+    let elapsed = started.elapsed();
+
+    println!(
+        "{DEPOSITS} disputes in {elapsed:?} ({:.0} tx/s)",
+        f64::from(DEPOSITS) / elapsed.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+/// Throughput check for the deposit path, run it with
+/// `cargo test --release -- --ignored --nocapture deposit_throughput`.
+#[test]
+#[ignore]
+fn deposit_throughput() -> Result<()> {
+    const DEPOSITS: u32 = 1_000_000;
+
+    let mut ledger = new_ledger();
+    let started = std::time::Instant::now();
+
+    for id in 0..DEPOSITS {
+        ledger.process_tx(Transaction {
+            client: (id % 1_000) as ClientId,
+            ..tx(
+                TransactionType::Deposit,
+                id as TransactionId,
+                Some(dec!(1.5)),
+            )
+        })?;
+    }
+
+    let elapsed = started.elapsed();
+
+    println!(
+        "{DEPOSITS} deposits in {elapsed:?} ({:.0} tx/s)",
+        f64::from(DEPOSITS) / elapsed.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn check_duplicate_transaction() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    let tx = tx(TransactionType::Deposit, 1, Some(dec!(50.0)));
+
+    ledger.process_tx(tx.clone())?;
+    let result = ledger.process_tx(tx.clone());
+
+    assert!(result.is_err());
+    assert!(matches!(result, Err(Error::DuplicateTransaction { tx: _ })));
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(50.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(50.0));
+    assert_eq!(ledger.tx_log.len(), 1);
+
+    Ok(())
+}
+
+/// Minimized from a failing case of the property tests below, the
+/// second deposit used to be credited too.
+#[test]
+fn deposit_reusing_an_id_with_another_amount_is_a_duplicate() -> Result<()> {
+    let mut ledger = new_ledger();
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(0.01))))?;
+
+    let result = ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(0.02))));
+
+    assert!(matches!(result, Err(Error::DuplicateTransaction { tx: _ })));
+
+    ledger.process_tx(tx(TransactionType::Dispute, 3, None))?;
+
+    let mut restored = restore_ledger(ledger.snapshot());
+    restored.process_tx(tx(TransactionType::Chargeback, 3, None))?;
+
+    let account = get_account(&restored, 1).expect("expected account for client.");
+
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(0.0));
+    assert_eq!(restored.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn duplicate_ids_are_rejected_whatever_their_fields() -> Result<()> {
+    let mut ledger = new_ledger();
+
+    ledger.process_tx(tx(TransactionType::Deposit, 1, Some(dec!(10.0))))?;
+    ledger.process_tx(tx(TransactionType::Deposit, 3, Some(dec!(1.0))))?;
+    ledger.process_tx(tx(TransactionType::Withdrawal, 2, Some(dec!(1.0))))?;
+
+    let reused = [
+        tx(TransactionType::Deposit, 1, Some(dec!(5.0))),
+        Transaction {
+            memo: Some("retry".to_string()),
+            ..tx(TransactionType::Deposit, 1, Some(dec!(10.0)))
+        },
+        tx(TransactionType::Withdrawal, 2, Some(dec!(2.0))),
+        tx(TransactionType::Deposit, 2, Some(dec!(2.0))),
+        tx(TransactionType::Withdrawal, 1, Some(dec!(2.0))),
+    ];
+
+    for tx in reused {
+        let result = ledger.process_tx(tx);
+
+        assert!(matches!(result, Err(Error::DuplicateTransaction { tx: _ })));
+    }
+
+    ledger.process_tx(tx(TransactionType::Dispute, 1, None))?;
+    ledger.process_tx(tx(TransactionType::Chargeback, 1, None))?;
+
+    let account = get_account(&ledger, 1).expect("expected account for client.");
+
+    assert!(account.locked);
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(0.0));
+    assert_eq!(account.total, dec!(0.0));
+    assert_eq!(ledger.verify_replay(), Ok(()));
+
+    Ok(())
+}
+
+/// Random sequences of transactions checked against the ledger
+/// invariants after every step.
+mod properties {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn any_tx() -> impl Strategy<Value = Transaction> {
+        let r#type = prop_oneof![
+            3 => Just(TransactionType::Deposit),
+            2 => Just(TransactionType::Withdrawal),
+            2 => Just(TransactionType::Dispute),
+            1 => Just(TransactionType::Resolve),
+            1 => Just(TransactionType::Chargeback),
+            1 => Just(TransactionType::ChargebackReversal),
+        ];
+
+        (r#type, 0..3u16, 0..12u8, 1..10_000i64, any::<bool>()).prop_map(
+            |(r#type, client, id, cents, partial)| {
+                let amount = match r#type {
+                    TransactionType::Deposit | TransactionType::Withdrawal => {
+                        Some(Decimal::new(cents, 2))
+                    }
+                    TransactionType::Dispute if partial => Some(Decimal::new(cents, 2)),
+                    _ => None,
+                };
+
+                Transaction {
+                    client,
+                    ..tx(r#type, TransactionId::from(id), amount)
+                }
+            },
+        )
+    }
+
+    /// Checks every account balances against its dispute state.
+    fn check_state(ledger: &Ledger) -> std::result::Result<(), TestCaseError> {
+        let mut held: HashMap<ClientId, Decimal> = HashMap::new();
+        let mut open: HashMap<ClientId, u32> = HashMap::new();
+
+        for (key, state) in ledger.disputes.iter() {
+            if *state == DisputeState::Disputed {
+                let amount = ledger
+                    .disputed_amounts
+                    .get(key)
+                    .or_else(|| ledger.deposits.get(key));
+
+                prop_assert!(amount.is_some(), "dispute without a deposit {key:?}");
+                *held.entry(key.0).or_default() += amount.copied().unwrap_or_default();
+                *open.entry(key.0).or_default() += 1;
+            }
+        }
+
+        for (client, acct) in ledger.accounts_iter() {
+            prop_assert!(acct.check_invariant(), "{acct:?}");
+            prop_assert!(acct.held >= Decimal::ZERO, "{acct:?}");
+            prop_assert_eq!(acct.held, held.get(client).copied().unwrap_or_default());
+            prop_assert_eq!(
+                ledger
+                    .open_disputes
+                    .get(client)
+                    .copied()
+                    .unwrap_or_default(),
+                open.get(client).copied().unwrap_or_default()
+            );
+        }
+
+        prop_assert_eq!(ledger.verify_replay(), Ok(()));
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn transactions_keep_balances_consistent(
+            txs in prop::collection::vec(any_tx(), 0..80)
+        ) {
+            let mut ledger = new_ledger();
+
+            for tx in txs {
+                // Rejected transactions must leave the state consistent
+                // just the same
+                let _ = ledger.process_tx(tx);
+                check_state(&ledger)?;
+            }
+        }
+    }
+}
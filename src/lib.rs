@@ -0,0 +1,53 @@
+//! Transaction processing engine behind the `txs` binary.
+//!
+//! Transactions are applied to a [`Ledger`], which keeps the accounts of
+//! every client:
+//!
+//! ```
+//! use rust_decimal::Decimal;
+//! use txs::{Ledger, Transaction, TransactionType};
+//!
+//! let mut ledger = Ledger::new();
+//!
+//! ledger
+//!     .process_tx(Transaction {
+//!         client: 1,
+//!         r#type: TransactionType::Deposit,
+//!         id: 1,
+//!         amount: Some(Decimal::ONE),
+//!         effective: None,
+//!         timestamp: None,
+//!         memo: None,
+//!         to: None,
+//!     })
+//!     .expect("deposit is applied");
+//!
+//! assert_eq!(ledger.accounts_summary()[0].available, Decimal::ONE);
+//! ```
+
+pub mod account;
+mod account_map;
+pub mod config;
+pub mod csv_io;
+pub mod error;
+pub mod event;
+pub mod filter;
+pub mod json_io;
+pub mod ledger;
+pub mod output;
+pub mod reconcile;
+pub mod report;
+pub mod snapshot;
+pub mod store;
+pub mod tx;
+
+pub use account::Account;
+pub use ledger::Ledger;
+pub use store::{MemoryStore, Store};
+pub use tx::{Transaction, TransactionType};
+
+pub type ClientId = u16;
+#[cfg(not(feature = "wide-tx-ids"))]
+pub type TransactionId = u32;
+#[cfg(feature = "wide-tx-ids")]
+pub type TransactionId = u64;
@@ -1,29 +1,65 @@
-mod account;
-mod csv_io;
-mod error;
-mod ledger;
-mod tx;
-
-use std::env::args;
-use std::fs::OpenOptions;
-
-use anyhow::{Result, bail};
-use futures::{StreamExt, TryStreamExt};
-use tracing::{Level, error};
+mod cli;
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write, stderr, stdout};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use chrono::Duration;
+use clap::Parser;
+use csv::Terminator;
+use futures::{StreamExt, TryStreamExt, future, stream};
+use tracing::{Instrument, Level, warn_span};
 use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+use txs::config::{DisputeExpiry, FeePolicy, LedgerConfig};
+use txs::csv_io::{
+    CsvReader, CsvReaderOptions, CsvWriter, CsvWriterOptions, InputFormat, read_accounts,
+};
+use txs::error::Error;
+use txs::event::NdjsonEventSink;
+use txs::filter::ClientFilter;
+use txs::json_io::{JsonReader, NdjsonWriter};
+use txs::output::{AccountWriter, OutputFormat, is_broken_pipe, write_records};
+use txs::report::{LockedAccount, ProcessingReport};
+use txs::tx::sort_by_effective_date;
+use txs::{Account, Ledger, Transaction};
 
-use crate::csv_io::{CsvReader, CsvWriter};
-use crate::ledger::Ledger;
+use crate::cli::{Cli, Command, DiffArgs, ReconcileArgs, RunArgs};
 
-pub type ClientId = u16;
-pub type TransactionId = u32;
+/// Exit code of a run that completed with rejected or unreadable
+/// transactions. Fatal errors, invalid arguments included, exit with 1.
+const EXIT_REJECTED: u8 = 2;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let err_log = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("error.log")?;
+async fn main() -> Result<ExitCode> {
+    // Clap exits with 2 on invalid arguments, which is taken by rejected
+    // transactions
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            err.print()?;
+
+            return Ok(if err.use_stderr() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            });
+        }
+    };
+    let err_log = if cli.error_log == Path::new("-") {
+        BoxMakeWriter::new(stderr)
+    } else {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&cli.error_log)?;
+
+        BoxMakeWriter::new(file)
+    };
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::WARN)
         .with_ansi(false)
@@ -31,41 +67,311 @@ async fn main() -> Result<()> {
         .finish();
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    let run_id = run_id();
+    eprintln!("txs run id: {run_id}");
 
-    let args = args().collect::<Vec<String>>();
+    // Spans below the subscriber level are disabled, the run span has to be
+    // at least `WARN` for its id to show up in the log.
+    let span = warn_span!("run", run_id = %run_id);
+    let result = match cli.command {
+        Some(Command::Diff(args)) => diff(args)
+            .instrument(span)
+            .await
+            .map(|()| ExitCode::SUCCESS),
+        Some(Command::Reconcile(args)) => reconcile(args)
+            .instrument(span)
+            .await
+            .map(|()| ExitCode::SUCCESS),
+        None => run(cli.run, &run_id).instrument(span).await,
+    };
 
-    if args.len() != 2 {
-        bail!("Usage: {} <input.csv>", args[0]);
+    match result {
+        // The reader went away (e.g. `txs input.csv | head`), there is no one
+        // left to report to so this is not a failure.
+        Err(err) if is_broken_pipe(&err) => Ok(ExitCode::SUCCESS),
+        result => result,
     }
+}
 
-    let input_path = &args[1];
+/// Identifies a single invocation so its output, events and error log lines
+/// can be tied together.
+fn run_id() -> String {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
 
-    let csv_reader = CsvReader::new(input_path)?;
-    let mut csv_stream = csv_reader.into_stream();
-    let mut ledger = Ledger::new();
+    format!("{:x}-{:x}", started.as_micros(), std::process::id())
+}
 
-    while let Some(mb_tx) = csv_stream.next().await {
-        match mb_tx {
-            Err(e) => {
-                error!("Error reading transaction: {:?}", e);
-                continue;
-            }
-            Ok(tx) => {
-                if let Err(e) = ledger.process_tx(tx) {
-                    error!("Error processing transaction {:?}", e);
+/// Processes the inputs, exiting with [`EXIT_REJECTED`] when any transaction
+/// could not be read or was rejected.
+async fn run(cli: RunArgs, run_id: &str) -> Result<ExitCode> {
+    let filter = ClientFilter::new(cli.allow_clients, cli.deny_clients, cli.strict_filters)?;
+
+    let options = CsvReaderOptions {
+        trim: cli.trim,
+        delimiter: if cli.tsv { b'\t' } else { cli.delimiter },
+        ignore_header_case: cli.ignore_header_case,
+    };
+    // Every input is opened upfront so a missing file fails the run before
+    // anything is applied
+    let readers = cli
+        .inputs
+        .iter()
+        .map(|input| match cli.input_format {
+            InputFormat::Csv => CsvReader::with_options(input, options).map(StreamExt::boxed),
+            InputFormat::Json => JsonReader::new(input).map(StreamExt::boxed),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let tx_stream = stream::iter(readers).flatten();
+
+    if cli.estimate_memory {
+        let estimate = Ledger::estimate_memory(tx_stream).await;
+        let mut out = output(cli.output.as_deref())?;
+        write_records(&mut out, cli.format, &[estimate])?;
+        out.flush()?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut tx_stream = tx_stream.boxed();
+    let dispute_expiry = match (cli.dispute_expiry_txs, cli.dispute_expiry_secs) {
+        (Some(count), _) => Some(DisputeExpiry::Transactions(count)),
+        (_, Some(secs)) => Some(DisputeExpiry::Elapsed(Duration::seconds(secs.into()))),
+        (None, None) => None,
+    };
+    let withdrawal_fee = match (cli.withdrawal_fee, cli.withdrawal_fee_percent) {
+        (Some(fee), _) => FeePolicy::Flat(fee),
+        (_, Some(percent)) => FeePolicy::Percentage(percent),
+        (None, None) => FeePolicy::None,
+    };
+    let config = LedgerConfig {
+        min_balance: cli.min_balance,
+        withdrawal_fee,
+        rounding: cli.rounding,
+        overdraft_limit: cli.overdraft_limit,
+        max_amount: cli.max_amount,
+        hot_accounts: cli.hot_accounts,
+        dense_accounts: cli.dense_accounts,
+        check_held: cli.check_held,
+        check_invariants: cli.check_invariants,
+        strict_support: cli.strict_support,
+        max_open_disputes_per_account: cli.max_open_disputes,
+        deposit_hold: cli.deposit_hold.map(|secs| Duration::seconds(secs.into())),
+        reject_out_of_order: cli.reject_out_of_order,
+        dispute_expiry,
+        auto_expire_disputes: dispute_expiry.is_some(),
+        lock_policy: cli.lock_policy,
+        first_tx_withdrawal_policy: cli.first_withdrawal,
+        max_errors: cli.max_errors,
+        strict: cli.strict,
+        // Interest only accrues through `Ledger::accrue_interest`, which
+        // the binary never calls
+        ..LedgerConfig::default()
+    };
+    let mut ledger = match &cli.resume {
+        Some(path) => {
+            let snapshot = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+            Ledger::restore_with_config(snapshot, config)
+        }
+        None => Ledger::with_config(config),
+    };
+    let mut baseline = None;
+
+    if let Some(path) = cli.events.as_ref().filter(|_| !cli.dry_run) {
+        let sink = NdjsonEventSink::new(BufWriter::new(File::create(path)?)).run_id(run_id);
+        ledger.set_event_sink(Box::new(sink));
+    }
+
+    if let Some(opening) = &cli.opening {
+        let accounts = read_accounts(opening)?;
+
+        if cli.only_changed {
+            let mut opening_ledger = Ledger::new();
+            opening_ledger.seed_opening(accounts.clone())?;
+            baseline = Some(opening_ledger);
+        }
+
+        ledger.seed_opening(accounts)?;
+    }
+
+    if cli.effective_order {
+        // Effective dates may point anywhere in the file, so every
+        // transaction has to be read before applying any of them. Unreadable
+        // records go first so they are logged and counted as usual.
+        let mut txs = Vec::new();
+        let mut unreadable = Vec::new();
+
+        while let Some(mb_tx) = tx_stream.next().await {
+            match mb_tx {
+                Err(e) if cli.strict => {
+                    return Err(Error::UnreadableTransaction {
+                        reason: format!("{e:#}"),
+                    }
+                    .into());
                 }
+                Err(e) => unreadable.push(e),
+                Ok(tx) => txs.push(tx),
             }
         }
+
+        sort_by_effective_date(&mut txs);
+        tx_stream = stream::iter(
+            unreadable
+                .into_iter()
+                .map(Err)
+                .chain(txs.into_iter().map(Ok)),
+        )
+        .boxed();
     }
 
-    let mut csv_writer = CsvWriter::new()?;
-    let accounts = ledger.accounts_summary();
+    let tx_stream = tx_stream.try_filter(|tx| future::ready(filter.is_allowed(tx.client)));
+    let report = ledger.process_transactions(tx_stream).await?;
 
-    for acct in accounts.into_iter() {
-        csv_writer.write(&acct)?;
+    match &cli.report {
+        Some(path) if path != Path::new("-") => {
+            serde_json::to_writer_pretty(File::create(path)?, &report)?;
+        }
+        Some(_) => print_report(&report)?,
+        None if cli.dry_run => print_report(&report)?,
+        None => {}
+    }
+
+    if cli.dry_run {
+        let errors = report.rejected + report.unreadable;
+
+        if errors > 0 {
+            eprintln!("{}", Error::DryRunRejected { errors });
+
+            return Ok(ExitCode::from(EXIT_REJECTED));
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.verify_replay {
+        ledger
+            .verify_replay()
+            .map_err(|clients| Error::ReplayMismatch { clients })?;
+    }
+
+    if let Some(path) = &cli.snapshot {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &ledger.snapshot())?;
+    }
+
+    if let Some(path) = &cli.risk_report {
+        serde_json::to_writer_pretty(File::create(path)?, &ledger.risk_report())?;
     }
 
-    csv_writer.flush()?;
+    if let Some(path) = &cli.locked_report {
+        let locked: Vec<LockedAccount> = ledger
+            .locked_accounts()
+            .into_iter()
+            .map(|client| LockedAccount { client })
+            .collect();
+
+        write_records(BufWriter::new(File::create(path)?), cli.format, &locked)?;
+    }
+
+    let out = output(cli.output.as_deref())?;
+    let mut writer: Box<dyn AccountWriter> = match cli.format {
+        OutputFormat::Csv => {
+            let mut options = CsvWriterOptions {
+                minor_units: cli.minor_units,
+                annotate_units: cli.annotate_units,
+                precision: cli.precision,
+                rounding: cli.rounding,
+                has_headers: !cli.no_header,
+                quote_style: cli.quote_style,
+                ..CsvWriterOptions::default()
+            };
+
+            if cli.crlf {
+                options.terminator = Terminator::CRLF;
+            }
+
+            Box::new(CsvWriter::with_options(out, options))
+        }
+        OutputFormat::Ndjson => Box::new(
+            NdjsonWriter::new(out)
+                .minor_units(cli.minor_units)
+                .precision(cli.precision)
+                .rounding(cli.rounding),
+        ),
+    };
+
+    let mut accounts = match &baseline {
+        Some(baseline) => ledger.changed_accounts(baseline),
+        None => ledger.accounts_summary(),
+    };
+
+    if cli.skip_empty {
+        accounts.retain(|acct| !acct.is_empty());
+    }
+
+    if let Some(key) = cli.sort_by {
+        key.sort(&mut accounts);
+    }
+
+    write_accounts(writer.as_mut(), accounts)?;
+
+    if report.rejected + report.unreadable > 0 {
+        return Ok(ExitCode::from(EXIT_REJECTED));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn diff(args: DiffArgs) -> Result<()> {
+    let mut before = Ledger::new();
+    before.process_reader(CsvReader::new(&args.before)?).await?;
+
+    let mut after = Ledger::new();
+    after.process_reader(CsvReader::new(&args.after)?).await?;
+
+    let mut out = BufWriter::new(stdout());
+    write_records(&mut out, args.format, &before.diff(&after))?;
+    out.flush()?;
+
+    Ok(())
+}
+
+async fn reconcile(args: ReconcileArgs) -> Result<()> {
+    let left: Vec<Transaction> = CsvReader::new(&args.left)?.try_collect().await?;
+    let right: Vec<Transaction> = CsvReader::new(&args.right)?.try_collect().await?;
+
+    let mut out = BufWriter::new(stdout());
+    write_records(
+        &mut out,
+        args.format,
+        &txs::reconcile::reconcile(&left, &right),
+    )?;
+    out.flush()?;
 
     Ok(())
 }
+
+fn print_report(report: &ProcessingReport) -> Result<()> {
+    serde_json::to_writer_pretty(stderr(), report)?;
+    eprintln!();
+
+    Ok(())
+}
+
+/// Opens the destination of the results, stdout unless a path is given.
+fn output(path: Option<&Path>) -> Result<BufWriter<Box<dyn Write>>> {
+    let out: Box<dyn Write> = match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    };
+
+    Ok(BufWriter::new(out))
+}
+
+fn write_accounts(writer: &mut dyn AccountWriter, accounts: Vec<Account>) -> Result<()> {
+    for acct in accounts.into_iter() {
+        writer.write(&acct)?;
+    }
+
+    writer.flush()
+}
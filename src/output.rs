@@ -0,0 +1,153 @@
+use std::io::{ErrorKind, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::account::Account;
+
+/// Destination for the accounts summary produced after processing.
+pub trait AccountWriter {
+    fn write(&mut self, record: &Account) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Comma separated values with a header row
+    #[default]
+    Csv,
+    /// One JSON object per account per line
+    Ndjson,
+}
+
+/// Order of the accounts in the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Client id ascending
+    Client,
+    /// Largest `total` first
+    TotalDesc,
+    /// Largest `available` first
+    AvailableDesc,
+}
+
+impl SortKey {
+    /// Sorts `accounts` by this key, ties are ordered by client id.
+    pub fn sort(self, accounts: &mut [Account]) {
+        match self {
+            SortKey::Client => accounts.sort_by_key(|acct| acct.id),
+            SortKey::TotalDesc => {
+                accounts.sort_by(|a, b| b.total.cmp(&a.total).then(a.id.cmp(&b.id)))
+            }
+            SortKey::AvailableDesc => {
+                accounts.sort_by(|a, b| b.available.cmp(&a.available).then(a.id.cmp(&b.id)))
+            }
+        }
+    }
+}
+
+/// Writes serializable report rows in the given format.
+pub fn write_records<W, T>(writer: W, format: OutputFormat, records: &[T]) -> Result<()>
+where
+    W: Write,
+    T: Serialize,
+{
+    match format {
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+
+            for record in records {
+                writer.serialize(record)?;
+            }
+
+            writer.flush()?;
+        }
+        OutputFormat::Ndjson => {
+            let mut writer = writer;
+
+            for record in records {
+                serde_json::to_writer(&mut writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the error comes from writing into a closed pipe, as happens when
+/// the output is piped into a program such as `head` which exits early.
+pub fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            return err.kind() == ErrorKind::BrokenPipe;
+        }
+
+        if let Some(err) = cause.downcast_ref::<serde_json::Error>() {
+            return err.io_error_kind() == Some(ErrorKind::BrokenPipe);
+        }
+
+        if let Some(err) = cause.downcast_ref::<csv::Error>()
+            && let csv::ErrorKind::Io(err) = err.kind()
+        {
+            return err.kind() == ErrorKind::BrokenPipe;
+        }
+
+        false
+    })
+}
+
+/// Writer whose reader went away, every write fails with a broken pipe.
+#[cfg(test)]
+pub(crate) struct ClosedPipe;
+
+#[cfg(test)]
+impl Write for ClosedPipe {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn sorts_by_balance_with_client_tiebreak() {
+        let account = |id, available, total| Account {
+            id: Some(id),
+            available,
+            held: total - available,
+            total,
+            locked: false,
+        };
+        let mut accounts = vec![
+            account(3, dec!(5.0), dec!(10.0)),
+            account(1, dec!(1.0), dec!(2.0)),
+            account(2, dec!(7.0), dec!(10.0)),
+        ];
+        let ids = |accounts: &[Account]| accounts.iter().map(|acct| acct.id).collect::<Vec<_>>();
+
+        SortKey::TotalDesc.sort(&mut accounts);
+
+        assert_eq!(ids(&accounts), vec![Some(2), Some(3), Some(1)]);
+
+        SortKey::AvailableDesc.sort(&mut accounts);
+
+        assert_eq!(ids(&accounts), vec![Some(2), Some(3), Some(1)]);
+
+        SortKey::Client.sort(&mut accounts);
+
+        assert_eq!(ids(&accounts), vec![Some(1), Some(2), Some(3)]);
+    }
+}
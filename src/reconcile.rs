@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::tx::{Transaction, TransactionType};
+use crate::{ClientId, TransactionId};
+
+/// How a transaction of one input compares with the other input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconcileStatus {
+    /// Only present in the left input
+    OnlyLeft,
+    /// Only present in the right input
+    OnlyRight,
+    /// Both inputs have the transaction id and type with differing fields
+    Conflict,
+}
+
+/// A transaction missing from one of the inputs or differing between them.
+/// The side a transaction is missing from has no client nor amount.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Reconciliation {
+    pub status: ReconcileStatus,
+    #[serde(rename = "type")]
+    pub r#type: TransactionType,
+    pub tx: TransactionId,
+    pub left_client: Option<ClientId>,
+    pub left_amount: Option<Decimal>,
+    pub right_client: Option<ClientId>,
+    pub right_amount: Option<Decimal>,
+}
+
+impl Reconciliation {
+    fn new(
+        status: ReconcileStatus,
+        left: Option<&Transaction>,
+        right: Option<&Transaction>,
+    ) -> Self {
+        let tx = left.or(right).expect("either side has the transaction");
+
+        Reconciliation {
+            status,
+            r#type: tx.r#type.clone(),
+            tx: tx.id,
+            left_client: left.map(|tx| tx.client),
+            left_amount: left.and_then(|tx| tx.amount),
+            right_client: right.map(|tx| tx.client),
+            right_amount: right.and_then(|tx| tx.amount),
+        }
+    }
+}
+
+/// Compares two inputs transaction by transaction, sorted by id and type.
+///
+/// Transactions are matched by id and type, identical ones on both sides
+/// are left out. Support rows share the id of the deposit they refer to, so
+/// an id repeats across types and may repeat within one (a deposit disputed
+/// twice), repeated rows are matched in input order.
+pub fn reconcile(left: &[Transaction], right: &[Transaction]) -> Vec<Reconciliation> {
+    type Sides<'a> = (Vec<&'a Transaction>, Vec<&'a Transaction>);
+
+    let mut by_key: BTreeMap<(TransactionId, TransactionType), Sides> = BTreeMap::new();
+
+    for tx in left {
+        by_key
+            .entry((tx.id, tx.r#type.clone()))
+            .or_default()
+            .0
+            .push(tx);
+    }
+
+    for tx in right {
+        by_key
+            .entry((tx.id, tx.r#type.clone()))
+            .or_default()
+            .1
+            .push(tx);
+    }
+
+    let mut entries = Vec::new();
+
+    for (_, (mut lefts, mut rights)) in by_key {
+        lefts.retain(|tx| match rights.iter().position(|other| other == tx) {
+            Some(pos) => {
+                rights.remove(pos);
+                false
+            }
+            None => true,
+        });
+
+        let paired = lefts.len().min(rights.len());
+
+        for (l, r) in lefts.iter().zip(rights.iter()) {
+            entries.push(Reconciliation::new(
+                ReconcileStatus::Conflict,
+                Some(l),
+                Some(r),
+            ));
+        }
+
+        for tx in &lefts[paired..] {
+            entries.push(Reconciliation::new(
+                ReconcileStatus::OnlyLeft,
+                Some(tx),
+                None,
+            ));
+        }
+
+        for tx in &rights[paired..] {
+            entries.push(Reconciliation::new(
+                ReconcileStatus::OnlyRight,
+                None,
+                Some(tx),
+            ));
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn identical_inputs_reconcile() {
+        let txs = vec![
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(1.0))),
+            Transaction::new(TransactionType::Dispute, 1, 1, None),
+        ];
+
+        assert!(reconcile(&txs, &txs).is_empty());
+    }
+
+    #[test]
+    fn repeated_support_rows_are_matched_in_order() {
+        let left = vec![
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(1.0))),
+            Transaction::new(TransactionType::Dispute, 1, 1, None),
+            Transaction::new(TransactionType::Resolve, 1, 1, None),
+            Transaction::new(TransactionType::Dispute, 1, 1, None),
+        ];
+        let right = left[..3].to_vec();
+
+        assert_eq!(
+            reconcile(&left, &right),
+            vec![Reconciliation {
+                status: ReconcileStatus::OnlyLeft,
+                r#type: TransactionType::Dispute,
+                tx: 1,
+                left_client: Some(1),
+                left_amount: None,
+                right_client: None,
+                right_amount: None,
+            }]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::account::{Account, serialize_amount};
+use crate::error::Error;
+use crate::tx::{Transaction, TransactionType};
+use crate::{ClientId, TransactionId};
+
+/// Outcome counts of driving a transaction stream through the ledger.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ProcessingReport {
+    /// Transactions applied to the ledger
+    pub applied: usize,
+    /// Transactions refused by the ledger
+    pub rejected: usize,
+    /// Records that could not be read as a transaction
+    pub unreadable: usize,
+    /// Transactions read per type, applied or not
+    pub by_type: BTreeMap<TransactionType, usize>,
+    /// Rejected transactions per [`Error::kind`]
+    pub errors: BTreeMap<&'static str, usize>,
+}
+
+impl ProcessingReport {
+    /// Tallies the outcome of processing a transaction of type `r#type`.
+    pub fn record(&mut self, r#type: TransactionType, result: &Result<(), Error>) {
+        *self.by_type.entry(r#type).or_default() += 1;
+
+        match result {
+            Ok(()) => self.applied += 1,
+            Err(e) => {
+                self.rejected += 1;
+                *self.errors.entry(e.kind()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Consolidated dispute and lock status for a single client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RiskEntry {
+    pub client: ClientId,
+    #[serde(serialize_with = "serialize_amount")]
+    pub held: Decimal,
+    pub open_disputes: u32,
+    pub locked: bool,
+    /// Transaction whose chargeback locked the account, the lowest id when
+    /// there are several
+    pub locked_by: Option<TransactionId>,
+}
+
+/// Client whose account ended locked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct LockedAccount {
+    pub client: ClientId,
+}
+
+/// Balance change of a client between two ledgers, amounts are the
+/// difference of the second ledger against the first one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BalanceDiff {
+    pub client: ClientId,
+    #[serde(serialize_with = "serialize_amount")]
+    pub available: Decimal,
+    #[serde(serialize_with = "serialize_amount")]
+    pub held: Decimal,
+    #[serde(serialize_with = "serialize_amount")]
+    pub total: Decimal,
+    pub locked_before: bool,
+    pub locked_after: bool,
+}
+
+/// Rough peak memory needed by the ledger maps for an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct MemoryEstimate {
+    /// Distinct clients in the input
+    pub clients: usize,
+    /// Transactions in the input
+    pub transactions: usize,
+    /// Estimated bytes held by the accounts map and the transaction log
+    pub bytes: usize,
+}
+
+impl MemoryEstimate {
+    /// Estimates the size of the hash tables holding `clients` accounts and
+    /// `transactions` log entries. This is a heuristic, allocator overhead
+    /// and the dispute bookkeeping are not accounted for.
+    pub fn from_counts(clients: usize, transactions: usize) -> Self {
+        let accounts = table_bytes(clients, size_of::<(ClientId, Account)>());
+        let tx_log = table_bytes(transactions, size_of::<Transaction>());
+
+        MemoryEstimate {
+            clients,
+            transactions,
+            bytes: accounts + tx_log,
+        }
+    }
+}
+
+/// Size of a hash table grown to fit `len` entries, tables keep a 7/8 load
+/// factor over a power of two number of buckets plus one control byte each.
+fn table_bytes(len: usize, entry: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+
+    let buckets = (len * 8).div_ceil(7).next_power_of_two();
+
+    buckets * (entry + 1)
+}
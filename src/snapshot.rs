@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::account::Account;
+use crate::ledger::{DepositHold, DisputeState};
+use crate::tx::Transaction;
+use crate::{ClientId, TransactionId};
+
+/// Serializable ledger state, restoring it resumes processing where the
+/// snapshot was taken.
+///
+/// Dispute states are part of the snapshot, so a dispute opened before the
+/// snapshot can still be resolved or charged back after restoring.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerSnapshot {
+    /// Accounts sorted by client id
+    pub accounts: Vec<Account>,
+    /// Every transaction applied to the ledger
+    pub transactions: Vec<Transaction>,
+    /// Dispute state of every disputed deposit, by client and transaction id
+    #[serde(default)]
+    pub disputes: Vec<(ClientId, TransactionId, DisputeState)>,
+    /// Portion held by each partial dispute, disputes missing here cover
+    /// the whole deposit
+    #[serde(default)]
+    pub disputed_amounts: Vec<(ClientId, TransactionId, Decimal)>,
+    /// Number of disputes currently open on each account
+    pub open_disputes: HashMap<ClientId, u32>,
+    /// Deposits of each account still under the settlement hold
+    pub deposit_holds: HashMap<ClientId, Vec<DepositHold>>,
+    /// Opening balances the ledger was seeded with
+    #[serde(default)]
+    pub opening: Vec<Account>,
+    /// Withdrawal fees charged so far
+    #[serde(default)]
+    pub fees_collected: Decimal,
+    /// Interest held by each open dispute, by client and transaction id
+    #[serde(default)]
+    pub held_interest: Vec<(ClientId, TransactionId, Decimal)>,
+    /// Interest credited to each account, less the interest forfeited by
+    /// chargebacks
+    #[serde(default)]
+    pub interest_accrued: HashMap<ClientId, Decimal>,
+    /// Fee charged by each withdrawal, by client and transaction id,
+    /// withdrawals missing here were charged none
+    #[serde(default)]
+    pub withdrawal_fees: Vec<(ClientId, TransactionId, Decimal)>,
+}
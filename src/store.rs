@@ -0,0 +1,87 @@
+use rust_decimal::Decimal;
+
+use crate::account::Account;
+use crate::error::Result;
+use crate::tx::Transaction;
+use crate::{ClientId, TransactionId};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// Persistence behind a [`Ledger`](crate::ledger::Ledger).
+///
+/// The ledger keeps working on its in-memory state, the store receives
+/// every applied transaction together with the accounts it changed, the
+/// writes of each transaction between [`Store::begin`] and
+/// [`Store::commit`]. Accounts and logged transactions missing from memory,
+/// e.g. after a restart, are read back from the store the first time a
+/// transaction refers to them.
+///
+/// Dispute state is not part of the store, a dispute opened before a
+/// restart can only be settled after restoring a
+/// [`LedgerSnapshot`](crate::snapshot::LedgerSnapshot).
+pub trait Store {
+    /// Whether the store keeps anything, the ledger skips every store call
+    /// and the cloning they need otherwise.
+    const PERSISTENT: bool = true;
+
+    /// Starts the writes of one transaction, none of them is kept unless
+    /// [`Store::commit`] follows.
+    fn begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Discards every write since [`Store::begin`].
+    fn rollback(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_account(&self, client: ClientId) -> Result<Option<Account>>;
+
+    fn upsert_account(&mut self, client: ClientId, account: &Account) -> Result<()>;
+
+    /// Appends `tx` to the stored log, support transactions included, with
+    /// the fee charged along with it, zero for anything but withdrawals.
+    fn insert_tx(&mut self, tx: &Transaction, fee: Decimal) -> Result<()>;
+
+    /// Finds the deposit, withdrawal, adjustment or transfer `id` of
+    /// `client` and the fee it was charged. Support transactions reuse the
+    /// id of the transaction they refer to and are never returned.
+    fn find_tx(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> Result<Option<(Transaction, Decimal)>>;
+}
+
+/// Default store of a ledger, state only lives in the ledger maps and is
+/// gone once the ledger is dropped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStore;
+
+impl Store for MemoryStore {
+    const PERSISTENT: bool = false;
+
+    fn get_account(&self, _: ClientId) -> Result<Option<Account>> {
+        Ok(None)
+    }
+
+    fn upsert_account(&mut self, _: ClientId, _: &Account) -> Result<()> {
+        Ok(())
+    }
+
+    fn insert_tx(&mut self, _: &Transaction, _: Decimal) -> Result<()> {
+        Ok(())
+    }
+
+    fn find_tx(&self, _: ClientId, _: TransactionId) -> Result<Option<(Transaction, Decimal)>> {
+        Ok(None)
+    }
+}
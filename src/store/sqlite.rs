@@ -0,0 +1,483 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, params};
+use rust_decimal::Decimal;
+
+use super::Store;
+use crate::account::Account;
+use crate::error::{Error, Result};
+use crate::tx::Transaction;
+use crate::{ClientId, TransactionId};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        client INTEGER PRIMARY KEY,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        total TEXT NOT NULL,
+        locked INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        support INTEGER NOT NULL,
+        body TEXT NOT NULL,
+        fee TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS transactions_by_id ON transactions (client, tx);
+";
+
+/// Keeps balances and the transaction log in a SQLite database, so a ledger
+/// picks up where it left after a restart.
+///
+/// Amounts are stored as decimal strings to keep their exact value and
+/// transactions as JSON, the row of each CSV record. The writes of each
+/// transaction are committed together.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and its tables as needed.
+    ///
+    /// A database where an account still holds funds is refused, the
+    /// disputes holding them are not stored and could never be settled.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path).map_err(storage)?)
+    }
+
+    /// Database living as long as the store, mostly useful for tests.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(storage)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA).map_err(storage)?;
+
+        let store = SqliteStore { conn };
+
+        if let Some(client) = store.holding_funds()? {
+            return Err(Error::Storage {
+                reason: format!("account {client} holds funds of disputes that are not stored"),
+            });
+        }
+
+        Ok(store)
+    }
+
+    /// First client whose stored account holds funds.
+    fn holding_funds(&self) -> Result<Option<ClientId>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT client, held FROM accounts ORDER BY client")
+            .map_err(storage)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, ClientId>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(storage)?;
+
+        for row in rows {
+            let (client, held) = row.map_err(storage)?;
+
+            if !parse_amount(&held)?.is_zero() {
+                return Ok(Some(client));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Store for SqliteStore {
+    fn begin(&mut self) -> Result<()> {
+        self.conn.execute_batch("BEGIN").map_err(storage)
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.conn.execute_batch("COMMIT").map_err(storage)
+    }
+
+    fn rollback(&mut self) -> Result<()> {
+        self.conn.execute_batch("ROLLBACK").map_err(storage)
+    }
+
+    fn get_account(&self, client: ClientId) -> Result<Option<Account>> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT available, held, total, locked FROM accounts WHERE client = ?1",
+                params![client],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, bool>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(storage)?;
+
+        let Some((available, held, total, locked)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(Account {
+            id: Some(client),
+            available: parse_amount(&available)?,
+            held: parse_amount(&held)?,
+            total: parse_amount(&total)?,
+            locked,
+        }))
+    }
+
+    fn upsert_account(&mut self, client: ClientId, account: &Account) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO accounts (client, available, held, total, locked)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (client) DO UPDATE SET
+                     available = excluded.available,
+                     held = excluded.held,
+                     total = excluded.total,
+                     locked = excluded.locked",
+                params![
+                    client,
+                    account.available.to_string(),
+                    account.held.to_string(),
+                    account.total.to_string(),
+                    account.locked,
+                ],
+            )
+            .map_err(storage)?;
+
+        Ok(())
+    }
+
+    fn insert_tx(&mut self, tx: &Transaction, fee: Decimal) -> Result<()> {
+        let body = serde_json::to_string(tx).map_err(storage)?;
+
+        self.conn
+            .execute(
+                "INSERT INTO transactions (client, tx, support, body, fee)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    tx.client,
+                    column_id(tx.id),
+                    tx.r#type.is_support(),
+                    body,
+                    fee.to_string(),
+                ],
+            )
+            .map_err(storage)?;
+
+        Ok(())
+    }
+
+    fn find_tx(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> Result<Option<(Transaction, Decimal)>> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT body, fee FROM transactions
+                 WHERE client = ?1 AND tx = ?2 AND support = 0
+                 ORDER BY seq LIMIT 1",
+                params![client, column_id(id)],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .map_err(storage)?;
+
+        let Some((body, fee)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some((
+            serde_json::from_str(&body).map_err(storage)?,
+            parse_amount(&fee)?,
+        )))
+    }
+}
+
+/// SQLite integers are signed 64 bits, wide ids above `i64::MAX` wrap
+/// around which still keeps them distinct.
+fn column_id(id: TransactionId) -> i64 {
+    id as i64
+}
+
+fn parse_amount(value: &str) -> Result<Decimal> {
+    Decimal::from_str(value).map_err(storage)
+}
+
+fn storage<E: std::fmt::Display>(err: E) -> Error {
+    Error::Storage {
+        reason: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use futures::StreamExt;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::config::{FeePolicy, LedgerConfig};
+    use crate::csv_io::{CsvReader, CsvReaderOptions};
+    use crate::ledger::Ledger;
+    use crate::tx::TransactionType;
+
+    /// Every CSV transactions input of the repository, fixtures and
+    /// conformance cases. Account files, expected outputs and opening
+    /// balances, are left out.
+    fn inputs() -> Vec<PathBuf> {
+        let mut inputs: Vec<PathBuf> = fs::read_dir("fixtures")
+            .expect("fixtures directory")
+            .map(|entry| entry.expect("fixture").path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+            .filter(|path| {
+                let name = path.to_string_lossy();
+                !name.ends_with(".out.csv") && !name.contains("/opening_")
+            })
+            .collect();
+
+        for case in fs::read_dir("tests/conformance").expect("conformance directory") {
+            inputs.push(case.expect("conformance case").path().join("input.csv"));
+        }
+
+        inputs.sort();
+        inputs
+    }
+
+    /// Reads `input` with the delimiter its name tells about.
+    fn reader(input: &Path) -> anyhow::Result<CsvReader> {
+        let delimiter = if input.to_string_lossy().ends_with("_semicolon.csv") {
+            b';'
+        } else {
+            b','
+        };
+
+        CsvReader::with_options(
+            input,
+            CsvReaderOptions {
+                delimiter,
+                ..CsvReaderOptions::default()
+            },
+        )
+    }
+
+    /// Database file in the temp directory, removed when dropped so a
+    /// failing test does not leave it behind.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("txs-{name}-{}.db", std::process::id()));
+            let _ = fs::remove_file(&path);
+
+            TempDb(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn processes_inputs_like_the_memory_store() -> anyhow::Result<()> {
+        for input in inputs() {
+            let mut memory = Ledger::new();
+            let mut sqlite =
+                Ledger::with_store(LedgerConfig::default(), SqliteStore::open_in_memory()?);
+
+            let expected = memory.process_reader(reader(&input)?).await?;
+            let report = sqlite.process_reader(reader(&input)?).await?;
+
+            assert_eq!(report, expected, "{}", input.display());
+            assert_eq!(
+                sqlite.accounts_summary(),
+                memory.accounts_summary(),
+                "{}",
+                input.display()
+            );
+
+            for acct in memory.accounts_summary() {
+                let client = acct.id.expect("summary rows have a client");
+
+                assert_eq!(sqlite.store().get_account(client)?, Some(acct));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resumes_after_reopening() -> anyhow::Result<()> {
+        let db = TempDb::new("store");
+
+        let mut ledger = Ledger::with_store(LedgerConfig::default(), SqliteStore::open(db.path())?);
+        ledger.process_tx(Transaction::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(dec!(10.0)),
+        ))?;
+        ledger.process_tx(Transaction::new(
+            TransactionType::Deposit,
+            2,
+            2,
+            Some(dec!(4.0)),
+        ))?;
+        drop(ledger);
+
+        let mut ledger = Ledger::with_store(LedgerConfig::default(), SqliteStore::open(db.path())?);
+        let replayed = ledger.process_tx(Transaction::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(dec!(10.0)),
+        ));
+
+        assert!(matches!(
+            replayed,
+            Err(Error::DuplicateTransaction { tx: _ })
+        ));
+
+        let results: Vec<Result<()>> = ledger
+            .process_stream(futures::stream::iter([
+                Ok(Transaction::new(
+                    TransactionType::Withdrawal,
+                    1,
+                    3,
+                    Some(dec!(2.5)),
+                )),
+                Ok(Transaction::new(TransactionType::Dispute, 2, 2, None)),
+            ]))
+            .collect()
+            .await;
+
+        assert!(results.iter().all(Result::is_ok), "{results:?}");
+        assert_eq!(
+            ledger.store().get_account(1)?.map(|acct| acct.available),
+            Some(dec!(7.5))
+        );
+        assert_eq!(
+            ledger.store().get_account(2)?.map(|acct| acct.held),
+            Some(dec!(4.0))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn replays_withdrawal_fees_after_reopening() -> anyhow::Result<()> {
+        let db = TempDb::new("fees");
+
+        let config = LedgerConfig {
+            withdrawal_fee: FeePolicy::Flat(dec!(1.0)),
+            ..LedgerConfig::default()
+        };
+        let mut ledger = Ledger::with_store(config, SqliteStore::open(db.path())?);
+        ledger.process_tx(Transaction::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(dec!(10.0)),
+        ))?;
+        ledger.process_tx(Transaction::new(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(dec!(2.0)),
+        ))?;
+        drop(ledger);
+
+        // The fee policy changed since, the logged withdrawals keep theirs
+        let mut ledger = Ledger::with_store(LedgerConfig::default(), SqliteStore::open(db.path())?);
+
+        for logged in [
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10.0))),
+            Transaction::new(TransactionType::Withdrawal, 1, 2, Some(dec!(2.0))),
+        ] {
+            assert!(matches!(
+                ledger.process_tx(logged),
+                Err(Error::DuplicateTransaction { tx: _ })
+            ));
+        }
+
+        assert_eq!(
+            ledger.store().get_account(1)?.map(|acct| acct.total),
+            Some(dec!(7.0))
+        );
+        assert_eq!(ledger.verify_replay(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rolled_back_writes_are_discarded() -> anyhow::Result<()> {
+        let mut store = SqliteStore::open_in_memory()?;
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10.0)));
+        let account = Account {
+            id: Some(1),
+            available: dec!(10.0),
+            held: dec!(0.0),
+            total: dec!(10.0),
+            locked: false,
+        };
+
+        store.begin()?;
+        store.upsert_account(1, &account)?;
+        store.insert_tx(&deposit, Decimal::ZERO)?;
+        store.rollback()?;
+
+        assert_eq!(store.get_account(1)?, None);
+        assert_eq!(store.find_tx(1, 1)?, None);
+
+        store.begin()?;
+        store.upsert_account(1, &account)?;
+        store.insert_tx(&deposit, Decimal::ZERO)?;
+        store.commit()?;
+
+        assert_eq!(store.get_account(1)?, Some(account));
+        assert_eq!(store.find_tx(1, 1)?, Some((deposit, Decimal::ZERO)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_a_database_holding_funds() -> anyhow::Result<()> {
+        let db = TempDb::new("held");
+
+        let mut ledger = Ledger::with_store(LedgerConfig::default(), SqliteStore::open(db.path())?);
+        ledger.process_tx(Transaction::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(dec!(10.0)),
+        ))?;
+        ledger.process_tx(Transaction::new(TransactionType::Dispute, 1, 1, None))?;
+        drop(ledger);
+
+        let reopened = SqliteStore::open(db.path());
+
+        assert!(matches!(reopened, Err(Error::Storage { reason: _ })));
+
+        Ok(())
+    }
+}
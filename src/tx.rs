@@ -1,10 +1,11 @@
+use chrono::{DateTime, NaiveDate, Utc};
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
 use crate::{ClientId, TransactionId};
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -12,9 +13,40 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Administrative correction (fees, interest, manual fixes) carrying a
+    /// signed amount applied to `available` and `total`
+    Adjustment,
+    /// Moves `amount` from the available funds of `client` to the account
+    /// of the `to` client
+    Transfer,
+    /// Reverses the chargeback of a deposit found not to be fraudulent after
+    /// all, crediting its funds back
+    #[serde(rename = "reversal")]
+    ChargebackReversal,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+impl TransactionType {
+    /// Support transactions reference a previous transaction and carry no
+    /// amount of their own.
+    pub fn is_support(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::ChargebackReversal
+        )
+    }
+}
+
+/// A row of the input CSV.
+///
+/// The `type` column selects the [`TransactionType`], an unknown type fails
+/// deserialization listing the accepted ones. Support rows (dispute, resolve
+/// and chargeback) usually leave `amount` empty, which reads as `None`, so
+/// one struct covers every row and the ledger validates the amount each type
+/// requires.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct Transaction {
     pub client: ClientId,
     pub r#type: TransactionType,
@@ -22,11 +54,141 @@ pub struct Transaction {
     pub id: TransactionId,
     #[serde(default)]
     pub amount: Option<Decimal>,
+    /// Date the transaction takes effect, when it differs from its position
+    /// in the input (e.g. backdated corrections).
+    #[serde(default, rename = "effective_date")]
+    pub effective: Option<NaiveDate>,
+    /// Moment the transaction happened, as an RFC 3339 timestamp.
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Free text note from the operator, e.g. why a dispute was filed. It
+    /// is kept in the transaction log and plays no part in balances.
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Destination client of a transfer, the source being `client`.
+    #[serde(default)]
+    pub to: Option<ClientId>,
 }
 
 impl Transaction {
+    /// Transaction of `client` with no effective date, timestamp, memo or
+    /// destination, as most tests need.
+    #[cfg(test)]
+    pub(crate) fn new(
+        r#type: TransactionType,
+        client: ClientId,
+        id: TransactionId,
+        amount: Option<Decimal>,
+    ) -> Self {
+        Transaction {
+            client,
+            r#type,
+            id,
+            amount,
+            effective: None,
+            timestamp: None,
+            memo: None,
+            to: None,
+        }
+    }
+
+    /// Retrieves the transaction amount, the error value is only built when
+    /// the amount is missing.
+    #[inline]
     pub fn amount(&self) -> Result<Decimal> {
         self.amount
-            .ok_or(Error::DomesticTransactionMissingAmount { tx: self.clone() })
+            .ok_or_else(|| Error::DomesticTransactionMissingAmount { tx: self.clone() })
+    }
+
+    /// Refuses a deposit or withdrawal read without an amount, e.g. from an
+    /// empty cell, so readers report it with its position. Support rows may
+    /// leave the amount out.
+    pub(crate) fn require_amount(self) -> Result<Self> {
+        match self.r#type {
+            TransactionType::Deposit | TransactionType::Withdrawal if self.amount.is_none() => {
+                Err(Error::DomesticTransactionMissingAmount { tx: self })
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Orders transactions by effective date, transactions without one come
+/// first. The sort is stable so same-day transactions keep their input order.
+pub fn sort_by_effective_date(txs: &mut [Transaction]) {
+    txs.sort_by_key(|tx| tx.effective);
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::{ReaderBuilder, Trim};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn parse(rows: &str) -> Vec<csv::Result<Transaction>> {
+        ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(format!("type, client, tx, amount\n{rows}").as_bytes())
+            .deserialize()
+            .collect()
+    }
+
+    #[test]
+    fn deserializes_every_type_from_the_type_column() -> csv::Result<()> {
+        let rows = "deposit, 1, 1, 1.5\n\
+                    withdrawal, 1, 2, 0.5\n\
+                    dispute, 1, 1,\n\
+                    resolve, 1, 1,\n\
+                    chargeback, 1, 1,\n\
+                    adjustment, 1, 3, -0.25\n\
+                    transfer, 1, 4, 0.75\n\
+                    reversal, 1, 1,\n";
+        let txs = parse(rows).into_iter().collect::<csv::Result<Vec<_>>>()?;
+        let parsed: Vec<(TransactionType, Option<Decimal>)> =
+            txs.into_iter().map(|tx| (tx.r#type, tx.amount)).collect();
+
+        assert_eq!(
+            parsed,
+            vec![
+                (TransactionType::Deposit, Some(dec!(1.5))),
+                (TransactionType::Withdrawal, Some(dec!(0.5))),
+                (TransactionType::Dispute, None),
+                (TransactionType::Resolve, None),
+                (TransactionType::Chargeback, None),
+                (TransactionType::Adjustment, Some(dec!(-0.25))),
+                (TransactionType::Transfer, Some(dec!(0.75))),
+                (TransactionType::ChargebackReversal, None),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn support_rows_may_omit_the_amount_column() -> csv::Result<()> {
+        let tx: Transaction = ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader("type, client, tx\ndispute, 2, 7\n".as_bytes())
+            .deserialize()
+            .next()
+            .expect("one row")?;
+
+        assert_eq!(tx.r#type, TransactionType::Dispute);
+        assert_eq!((tx.client, tx.id, tx.amount), (2, 7, None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_type_is_reported() {
+        let result = parse("refund, 1, 1, 1.0\n").remove(0);
+        let err = result.expect_err("refund is not a transaction type");
+
+        assert!(
+            err.to_string().contains("unknown variant `refund`"),
+            "unexpected error: {err}"
+        );
     }
 }
@@ -0,0 +1,465 @@
+//! Runs the binary with command line options whose effect is only visible
+//! from outside the process.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Working directory for a test, the binary writes its error log there.
+fn workdir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("cli")
+        .join(name);
+    fs::create_dir_all(&dir).expect("test working directory");
+    dir
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(name)
+}
+
+/// Runs the binary expecting it to exit with `code`, fixtures rejecting
+/// some transactions exit with 2 but still write their accounts.
+fn run_txs(args: &[&str], dir: &Path, code: i32) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("running txs");
+
+    assert_eq!(
+        output.status.code(),
+        Some(code),
+        "txs exited with {}",
+        output.status
+    );
+    String::from_utf8(output.stdout).expect("utf-8 output")
+}
+
+#[test]
+fn output_is_identical_across_runs() {
+    let dir = workdir("deterministic");
+    let input = fixture("many_clients.csv");
+    let input = input.to_str().expect("utf-8 path");
+
+    let first = run_txs(&[input], &dir, 2);
+    let second = run_txs(&[input], &dir, 2);
+
+    assert_eq!(first, second);
+
+    let clients: Vec<u16> = first
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().parse().unwrap())
+        .collect();
+
+    assert_eq!(clients, (1..=24).collect::<Vec<u16>>());
+}
+
+#[test]
+fn reads_transactions_from_stdin() {
+    let dir = workdir("stdin");
+    let input = fs::read(fixture("sample_01.csv")).expect("fixture");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg("-")
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("running txs");
+
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(&input)
+        .expect("writing stdin");
+
+    let output = child.wait_with_output().expect("waiting for txs");
+
+    // The second withdrawal of sample_01 exceeds the funds of client 2
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "txs exited with {}",
+        output.status
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).expect("utf-8 output"),
+        fs::read_to_string(fixture("sample_01.out.csv")).expect("expected output")
+    );
+}
+
+#[test]
+fn exit_code_reflects_run_outcome() {
+    let dir = workdir("exit_code");
+    let status = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_txs"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("running txs")
+            .status
+            .code()
+    };
+    let clean = fixture("daily_1.csv");
+    let rejected = fixture("sample_01.csv");
+
+    assert_eq!(status(&[clean.to_str().expect("utf-8 path")]), Some(0));
+    assert_eq!(status(&[rejected.to_str().expect("utf-8 path")]), Some(2));
+    assert_eq!(status(&["missing.csv"]), Some(1));
+    assert_eq!(status(&["--no-such-flag"]), Some(1));
+}
+
+#[test]
+fn negative_withdrawal_fees_are_refused() {
+    let dir = workdir("negative_fee");
+    let input = fixture("daily_1.csv");
+
+    for flag in ["--withdrawal-fee=-1", "--withdrawal-fee-percent=-0.5"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+            .arg(&input)
+            .arg(flag)
+            .current_dir(&dir)
+            .output()
+            .expect("running txs");
+
+        assert_eq!(output.status.code(), Some(1), "{flag}");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("non-negative"),
+            "{flag}"
+        );
+    }
+}
+
+#[test]
+fn strict_mode_fails_on_bad_row() {
+    let dir = workdir("strict");
+    let input = fixture("corrupt.csv");
+
+    let lenient = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(&input)
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert_eq!(
+        lenient.status.code(),
+        Some(2),
+        "txs exited with {}",
+        lenient.status
+    );
+
+    let strict = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(&input)
+        .arg("--strict")
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+
+    assert!(!strict.status.success());
+    assert!(strict.stdout.is_empty());
+    assert!(
+        stderr.contains("Transaction could not be read"),
+        "unexpected stderr: {stderr}"
+    );
+}
+
+#[test]
+fn error_log_flag_sets_log_destination() {
+    let dir = workdir("error_log");
+    let path = dir.join("custom.log");
+    let _ = fs::remove_file(&path);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("corrupt.csv"))
+        .arg("--error-log")
+        .arg(&path)
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "txs exited with {}",
+        output.status
+    );
+    assert!(
+        fs::read_to_string(&path)
+            .expect("error log")
+            .contains("Error reading transaction")
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("corrupt.csv"))
+        .arg("--error-log")
+        .arg("-")
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error reading transaction"));
+}
+
+#[test]
+fn effective_order_counts_unreadable_rows() {
+    let dir = workdir("effective_order");
+    let input = fixture("malformed_row.csv");
+    let input = input.to_str().expect("utf-8 path");
+
+    assert_eq!(
+        run_txs(&[input, "--effective-order"], &dir, 2),
+        run_txs(&[input], &dir, 2)
+    );
+}
+
+#[test]
+fn error_log_lines_carry_the_run_id() {
+    let dir = workdir("run_id");
+    let path = dir.join("error.log");
+    let _ = fs::remove_file(&path);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("corrupt.csv"))
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let run_id = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("txs run id: "))
+        .expect("run id on stderr");
+    let log = fs::read_to_string(&path).expect("error log");
+    let span = format!("run{{run_id={run_id}}}");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(log.lines().count(), 3, "{log}");
+    assert!(log.lines().all(|line| line.contains(&span)), "{log}");
+}
+
+#[test]
+fn skip_empty_keeps_funded_and_locked_accounts() {
+    let dir = workdir("skip_empty");
+    let input = fixture("skip_empty.csv");
+    let input = input.to_str().expect("utf-8 path");
+
+    assert_eq!(
+        run_txs(&[input, "--skip-empty"], &dir, 0),
+        "client,available,held,total,locked\n\
+         2,2.0000,0.0000,2.0000,false\n\
+         3,0.0000,0.0000,0.0000,true\n"
+    );
+    assert_eq!(run_txs(&[input], &dir, 0).lines().count(), 4);
+}
+
+#[test]
+fn tsv_flag_reads_tab_separated_input() {
+    let dir = workdir("tsv");
+    let csv = fixture("sample_01.csv");
+    let tsv = fixture("sample_01.tsv");
+
+    assert_eq!(
+        run_txs(&[tsv.to_str().expect("utf-8 path"), "--tsv"], &dir, 2),
+        run_txs(&[csv.to_str().expect("utf-8 path")], &dir, 2)
+    );
+}
+
+#[test]
+fn json_input_format_reads_transaction_arrays() {
+    let dir = workdir("json_input");
+    let csv = fixture("sample_01.csv");
+    let json = fixture("sample_01.json");
+
+    assert_eq!(
+        run_txs(
+            &[json.to_str().expect("utf-8 path"), "--input-format", "json"],
+            &dir,
+            2,
+        ),
+        run_txs(&[csv.to_str().expect("utf-8 path")], &dir, 2)
+    );
+}
+
+#[test]
+fn dry_run_reports_errors_without_writing() {
+    let dir = workdir("dry_run");
+    let output_path = dir.join("accounts.csv");
+    let events_path = dir.join("events.ndjson");
+    let _ = fs::remove_file(&output_path);
+    let _ = fs::remove_file(&events_path);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("corrupt.csv"))
+        .arg("--dry-run")
+        .arg("--output")
+        .arg(&output_path)
+        .arg("--events")
+        .arg(&events_path)
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains("\"rejected\": 1"), "{stderr}");
+    assert!(stderr.contains("\"unreadable\": 2"), "{stderr}");
+    assert!(stderr.contains("Dry run found 3 transactions that would not apply"));
+    assert!(!output_path.exists());
+    assert!(!events_path.exists());
+
+    // A clean input passes
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("sample_02.csv"))
+        .arg("--dry-run")
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert!(output.status.success(), "txs exited with {}", output.status);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn locked_report_lists_locked_clients() {
+    let dir = workdir("locked_report");
+    let input = fixture("skip_empty.csv");
+    let report = dir.join("locked.csv");
+    let _ = fs::remove_file(&report);
+
+    let stdout = run_txs(
+        &[
+            input.to_str().expect("utf-8 path"),
+            "--locked-report",
+            report.to_str().expect("utf-8 path"),
+        ],
+        &dir,
+        0,
+    );
+
+    assert_eq!(stdout.lines().count(), 4);
+    assert_eq!(
+        fs::read_to_string(&report).expect("locked report"),
+        "client\n3\n"
+    );
+}
+
+#[test]
+fn reconcile_reports_rows_missing_or_differing() {
+    let dir = workdir("reconcile");
+    let left = fixture("reconcile_left.csv");
+    let right = fixture("reconcile_right.csv");
+
+    let stdout = run_txs(
+        &[
+            "reconcile",
+            left.to_str().expect("utf-8 path"),
+            right.to_str().expect("utf-8 path"),
+        ],
+        &dir,
+        0,
+    );
+
+    assert_eq!(
+        stdout,
+        "status,type,tx,left_client,left_amount,right_client,right_amount\n\
+         conflict,deposit,2,2,2,2,2.5\n\
+         only_left,withdrawal,3,1,0.5,,\n\
+         only_right,deposit,4,,,3,3\n"
+    );
+}
+
+#[test]
+fn resuming_a_snapshot_matches_a_single_run() {
+    let dir = workdir("snapshot");
+    let input = fs::read_to_string(fixture("sample_03.csv")).expect("fixture");
+    let lines: Vec<&str> = input.lines().collect();
+    let (first, second) = (dir.join("first.csv"), dir.join("second.csv"));
+    let snapshot = dir.join("snapshot.json");
+
+    // The dispute is opened before the checkpoint and charged back after it
+    fs::write(&first, lines[..5].join("\n")).expect("first half");
+    fs::write(&second, format!("{}\n{}", lines[0], lines[5..].join("\n"))).expect("second half");
+
+    let path = |path: &Path| path.to_str().expect("utf-8 path").to_string();
+    let expected = run_txs(&[&path(&fixture("sample_03.csv"))], &dir, 0);
+
+    run_txs(&[&path(&first), "--snapshot", &path(&snapshot)], &dir, 0);
+    let resumed = run_txs(&[&path(&second), "--resume", &path(&snapshot)], &dir, 0);
+
+    assert_eq!(resumed, expected);
+
+    // Opening balances would overwrite the restored accounts
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(&second)
+        .arg("--resume")
+        .arg(&snapshot)
+        .arg("--opening")
+        .arg(fixture("opening_01.csv"))
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn inputs_are_applied_in_order_to_one_ledger() {
+    let dir = workdir("multiple_inputs");
+    let (first, second) = (fixture("daily_1.csv"), fixture("daily_2.csv"));
+
+    // The second file replays deposit 1, which is rejected, and disputes it
+    let stdout = run_txs(
+        &[
+            first.to_str().expect("utf-8 path"),
+            second.to_str().expect("utf-8 path"),
+        ],
+        &dir,
+        2,
+    );
+
+    assert_eq!(
+        stdout,
+        "client,available,held,total,locked\n\
+         1,2.5000,10.0000,12.5000,false\n\
+         2,3.0000,0.0000,3.0000,false\n"
+    );
+}
+
+#[test]
+fn output_flag_writes_accounts_to_file() {
+    let dir = workdir("output");
+    let path = dir.join("accounts.csv");
+    let _ = fs::remove_file(&path);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+        .arg(fixture("sample_01.csv"))
+        .arg("--output")
+        .arg(&path)
+        .arg("--sort-by")
+        .arg("client")
+        .current_dir(&dir)
+        .output()
+        .expect("running txs");
+
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "txs exited with {}",
+        output.status
+    );
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        fs::read_to_string(&path).expect("output file"),
+        "client,available,held,total,locked\n\
+         1,1.5000,0.0000,1.5000,false\n\
+         2,2.0000,0.0000,2.0000,false\n"
+    );
+}
@@ -0,0 +1,56 @@
+//! Runs the binary over every case in `tests/conformance`. A case is a
+//! directory holding an `input.csv` and the `expected.csv` output, adding a
+//! regression case only takes dropping those two files in a new directory.
+//! Accounts are written in client id order, so outputs are compared as is.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn outputs_match_expected_files() {
+    let cases = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut checked = 0;
+
+    for entry in fs::read_dir(&cases).expect("conformance cases directory") {
+        let case = entry.expect("conformance case").path();
+
+        if !case.is_dir() {
+            continue;
+        }
+
+        let name = case.file_name().unwrap().to_string_lossy().into_owned();
+        let expected = fs::read_to_string(case.join("expected.csv"))
+            .unwrap_or_else(|e| panic!("{name}: reading expected.csv: {e}"));
+
+        // The binary writes its error log to the working directory
+        let workdir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("conformance")
+            .join(&name);
+        fs::create_dir_all(&workdir).expect("conformance working directory");
+
+        let output = Command::new(env!("CARGO_BIN_EXE_txs"))
+            .arg(case.join("input.csv"))
+            .current_dir(&workdir)
+            .output()
+            .unwrap_or_else(|e| panic!("{name}: running txs: {e}"));
+
+        // Cases rejecting transactions exit with 2
+        assert!(
+            matches!(output.status.code(), Some(0 | 2)),
+            "{name}: txs exited with {}",
+            output.status
+        );
+
+        let actual = String::from_utf8(output.stdout).expect("utf-8 output");
+
+        assert_eq!(actual, expected, "{name}");
+        checked += 1;
+    }
+
+    assert!(
+        checked > 0,
+        "no conformance cases found in {}",
+        cases.display()
+    );
+}